@@ -0,0 +1,152 @@
+//! A turn's pathing for a crowd of monsters after the player: each running its own A*
+//! search, as `MonsterAI` used to, against sharing the turn's Dijkstra maps.
+
+use std::hint::black_box;
+
+use bracket_lib::prelude::{a_star_search, Algorithm2D, BaseMap, DistanceAlg, Point, SmallVec};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use itertools::Itertools;
+
+#[path = "../src/ai_pathing.rs"]
+mod ai_pathing;
+
+use ai_pathing::AiPathing;
+
+const WIDTH: i32 = 80;
+const HEIGHT: i32 = 43;
+/// How far an archer shoots.
+const BOW_RANGE: u16 = 6;
+
+/// A level-sized room dotted with pillars, each monster in it blocking its tile.
+struct Arena {
+    walls: Vec<bool>,
+    blocked: Vec<bool>,
+    player_ix: usize,
+    monster_ixs: Vec<usize>,
+}
+
+impl Arena {
+    fn new(monster_count: usize) -> Self {
+        let is_wall = |(xx, yy): (i32, i32)| {
+            xx == 0
+                || yy == 0
+                || xx == WIDTH - 1
+                || yy == HEIGHT - 1
+                || (xx % 6 == 3 && yy % 4 == 2)
+        };
+        let walls: Vec<bool> = (0..HEIGHT)
+            .cartesian_product(0..WIDTH)
+            .map(|(yy, xx)| is_wall((xx, yy)))
+            .collect();
+        let player_ix = (HEIGHT / 2 * WIDTH + WIDTH / 2) as usize;
+        // Spread out over the floor, the way rooms full of spawns would be
+        let floor: Vec<usize> = walls
+            .iter()
+            .positions(|wall| !wall)
+            .filter(|ix| *ix != player_ix)
+            .collect();
+        let monster_ixs: Vec<usize> = floor
+            .iter()
+            .copied()
+            .step_by(floor.len() / monster_count)
+            .take(monster_count)
+            .collect();
+        let mut blocked = walls.clone();
+        monster_ixs.iter().for_each(|ix| blocked[*ix] = true);
+        Arena {
+            walls,
+            blocked,
+            player_ix,
+            monster_ixs,
+        }
+    }
+}
+
+impl BaseMap for Arena {
+    fn is_opaque(&self, ix: usize) -> bool {
+        self.walls[ix]
+    }
+
+    fn get_available_exits(&self, ix: usize) -> SmallVec<[(usize, f32); 10]> {
+        let here = self.index_to_point2d(ix);
+        (-1..=1)
+            .cartesian_product(-1..=1)
+            .filter(|delta| *delta != (0, 0))
+            .map(|(dx, dy)| here + Point::new(dx, dy))
+            .filter(|there| self.in_bounds(*there))
+            .map(|there| {
+                (
+                    self.point2d_to_index(there),
+                    DistanceAlg::Pythagoras.distance2d(here, there),
+                )
+            })
+            .filter(|(there_ix, _)| !self.blocked[*there_ix])
+            .collect()
+    }
+
+    fn get_pathing_distance(&self, ix1: usize, ix2: usize) -> f32 {
+        DistanceAlg::Pythagoras.distance2d(self.index_to_point2d(ix1), self.index_to_point2d(ix2))
+    }
+}
+
+impl Algorithm2D for Arena {
+    fn dimensions(&self) -> Point {
+        Point::new(WIDTH, HEIGHT)
+    }
+}
+
+fn monster_turn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("monster turn");
+    [10, 50, 200].into_iter().for_each(|monster_count| {
+        let arena = Arena::new(monster_count);
+        group.bench_with_input(
+            BenchmarkId::new("a_star", monster_count),
+            &arena,
+            |b, arena| {
+                b.iter(|| {
+                    arena.monster_ixs.iter().for_each(|ix| {
+                        black_box(a_star_search(*ix, arena.player_ix, arena));
+                    })
+                })
+            },
+        );
+        let mut pathing = AiPathing::default();
+        [("approach", 0), ("approach to range", BOW_RANGE)]
+            .into_iter()
+            .for_each(|(name, range)| {
+                group.bench_with_input(
+                    BenchmarkId::new(name, monster_count),
+                    &arena,
+                    |b, arena| {
+                        b.iter(|| {
+                            pathing.new_turn();
+                            arena.monster_ixs.iter().for_each(|ix| {
+                                black_box(pathing.approach_step(
+                                    arena,
+                                    *ix,
+                                    arena.player_ix,
+                                    range,
+                                ));
+                            })
+                        })
+                    },
+                );
+            });
+        group.bench_with_input(
+            BenchmarkId::new("flee", monster_count),
+            &arena,
+            |b, arena| {
+                b.iter(|| {
+                    pathing.new_turn();
+                    arena.monster_ixs.iter().for_each(|ix| {
+                        black_box(pathing.flee_step(arena, *ix, &[arena.player_ix]));
+                    })
+                })
+            },
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(benches, monster_turn);
+criterion_main!(benches);
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use bracket_lib::prelude::{Algorithm2D, BaseMap, DijkstraMap, DistanceAlg, Point};
+use itertools::Itertools;
+
+/// How far a map towards a target reaches; further than any path across a level.
+const APPROACH_DEPTH: f32 = 1000.0;
+/// How far from its foes a flee map looks for a way out.
+const FLEE_DEPTH: f32 = 100.0;
+
+/// This turn's Dijkstra maps, shared by every monster heading for, or running from, the
+/// same place, instead of each searching for its own path. A map is built the first time
+/// anyone asks for it, and `new_turn` throws them all away, since by then everyone has moved.
+#[derive(Default)]
+pub struct AiPathing {
+    /// Towards a tile, until within a range of it
+    approach_maps: HashMap<(usize, u16), DijkstraMap>,
+    /// Away from a set of tiles, in order
+    flee_maps: HashMap<Vec<usize>, DijkstraMap>,
+}
+
+impl AiPathing {
+    pub fn new_turn(&mut self) {
+        self.approach_maps.clear();
+        self.flee_maps.clear();
+    }
+
+    /// The next tile from `ix` on the way to within `range` of `target_ix`; none if it is
+    /// already there or can get no closer.
+    pub fn approach_step<M: BaseMap + Algorithm2D>(
+        &mut self,
+        map: &M,
+        ix: usize,
+        target_ix: usize,
+        range: u16,
+    ) -> Option<usize> {
+        let approach_map = &*self
+            .approach_maps
+            .entry((target_ix, range))
+            .or_insert_with(|| {
+                dijkstra_map(map, &within_range(map, target_ix, range), APPROACH_DEPTH)
+            });
+        DijkstraMap::find_lowest_exit(approach_map, ix, map)
+            .filter(|exit| approach_map.map[*exit] < distance_at(approach_map, map, ix))
+    }
+
+    /// The next tile from `ix` further from all of `threat_ixs`; none if cornered.
+    pub fn flee_step<M: BaseMap + Algorithm2D>(
+        &mut self,
+        map: &M,
+        ix: usize,
+        threat_ixs: &[usize],
+    ) -> Option<usize> {
+        let flee_map = &*self
+            .flee_maps
+            .entry(threat_ixs.iter().copied().sorted().collect())
+            .or_insert_with(|| dijkstra_map(map, threat_ixs, FLEE_DEPTH));
+        DijkstraMap::find_highest_exit(flee_map, ix, map)
+            .filter(|exit| flee_map.map[*exit] > distance_at(flee_map, map, ix))
+    }
+}
+
+fn dijkstra_map<M: BaseMap + Algorithm2D>(map: &M, starts: &[usize], depth: f32) -> DijkstraMap {
+    let size = map.dimensions();
+    DijkstraMap::new(size.x, size.y, starts, map, depth)
+}
+
+/// How far `ix` is by `dijkstra_map`. Whoever stands there blocks the tile, which leaves it
+/// off the map unless it is a start, so it is judged by its open neighbours instead.
+fn distance_at(dijkstra_map: &DijkstraMap, map: &dyn BaseMap, ix: usize) -> f32 {
+    map.get_available_exits(ix)
+        .iter()
+        .map(|(exit, cost)| dijkstra_map.map[*exit] + cost)
+        .fold(dijkstra_map.map[ix], f32::min)
+}
+
+/// The target's tile and every open one no further than `range` from it.
+fn within_range<M: BaseMap + Algorithm2D>(map: &M, target_ix: usize, range: u16) -> Vec<usize> {
+    let target = map.index_to_point2d(target_ix);
+    let reach = i32::from(range);
+    (-reach..=reach)
+        .cartesian_product(-reach..=reach)
+        .map(|(dx, dy)| target + Point::new(dx, dy))
+        .filter(|point| {
+            map.in_bounds(*point)
+                && DistanceAlg::Pythagoras.distance2d(target, *point) <= f32::from(range)
+        })
+        .map(|point| map.point2d_to_index(point))
+        .filter(|ix| *ix == target_ix || !map.is_opaque(*ix))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bracket_lib::prelude::SmallVec;
+
+    use super::*;
+
+    /// An open room, with whoever is in it blocking their tiles.
+    struct Room {
+        size: i32,
+        blocked: Vec<bool>,
+    }
+
+    impl Room {
+        fn new(size: i32) -> Self {
+            Room {
+                size,
+                blocked: vec![false; (size * size) as usize],
+            }
+        }
+
+        fn ix(&self, xx: i32, yy: i32) -> usize {
+            self.point2d_to_index(Point::new(xx, yy))
+        }
+    }
+
+    impl BaseMap for Room {
+        fn is_opaque(&self, _ix: usize) -> bool {
+            false
+        }
+
+        fn get_available_exits(&self, ix: usize) -> SmallVec<[(usize, f32); 10]> {
+            let here = self.index_to_point2d(ix);
+            (-1..=1)
+                .cartesian_product(-1..=1)
+                .filter(|(dx, dy)| (*dx, *dy) != (0, 0))
+                .map(|(dx, dy)| here + Point::new(dx, dy))
+                .filter(|there| self.in_bounds(*there))
+                .map(|there| {
+                    (
+                        self.point2d_to_index(there),
+                        DistanceAlg::Pythagoras.distance2d(here, there),
+                    )
+                })
+                .filter(|(there_ix, _)| !self.blocked[*there_ix])
+                .collect()
+        }
+    }
+
+    impl Algorithm2D for Room {
+        fn dimensions(&self) -> Point {
+            Point::new(self.size, self.size)
+        }
+    }
+
+    #[test]
+    fn monsters_close_to_range_and_run_away() {
+        let mut room = Room::new(10);
+        let target_ix = room.ix(1, 5);
+        let monster_ix = room.ix(8, 5);
+        room.blocked[monster_ix] = true;
+        let mut pathing = AiPathing::default();
+
+        assert_eq!(
+            pathing.approach_step(&room, monster_ix, target_ix, 0),
+            Some(room.ix(7, 5))
+        );
+        // An archer with a range of three stops once it is three tiles off
+        assert_eq!(
+            pathing.approach_step(&room, monster_ix, target_ix, 3),
+            Some(room.ix(7, 5))
+        );
+        assert_eq!(
+            pathing.approach_step(&room, room.ix(4, 5), target_ix, 3),
+            None
+        );
+
+        let fled_to = pathing.flee_step(&room, monster_ix, &[target_ix]);
+        assert_eq!(fled_to.map(|ix| room.index_to_point2d(ix).x), Some(9));
+        // Backed into a corner, there is nowhere further to go
+        let cornered_ix = room.ix(9, 9);
+        room.blocked[cornered_ix] = true;
+        assert_eq!(
+            pathing.flee_step(&room, cornered_ix, &[room.ix(5, 5)]),
+            None
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt::Display;
+
+/// `count` dice of `sides` sides each, e.g. 2d4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dice {
+    pub count: u16,
+    pub sides: u16,
+}
+
+impl Dice {
+    pub const fn new(count: u16, sides: u16) -> Self {
+        Dice { count, sides }
+    }
+
+    /// Sums the dice; `roll_die(sides)` gives a number in `1..=sides`.
+    pub fn roll(&self, roll_die: &mut impl FnMut(u16) -> u16) -> u16 {
+        (0..self.count).map(|_| roll_die(self.sides)).sum()
+    }
+}
+
+impl Display for Dice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)
+    }
+}
+
+/// Fists, claws and teeth.
+pub const UNARMED_DICE: Dice = Dice::new(1, 4);
+const ATTACK_DIE: u16 = 20;
+/// An attack hits on `ATTACK_DIE + attack_bonus >= BASE_DEFENSE + defense`.
+const BASE_DEFENSE: i16 = 10;
+
+/// Everything about the attacker and defender that goes into an attack.
+#[derive(Clone, Copy, Debug)]
+pub struct Attack {
+    pub attack_bonus: i16,
+    pub defense: i16,
+    pub dice: Dice,
+    pub damage_bonus: i16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackOutcome {
+    /// A natural 1 always misses
+    Fumble,
+    Miss,
+    Hit,
+    /// A natural 20 always hits, rolling the damage dice twice
+    Critical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttackResult {
+    pub outcome: AttackOutcome,
+    pub attack_roll: u16,
+    pub attack_total: i16,
+    /// What the attack total needed to reach
+    pub target: i16,
+    pub dice_roll: u16,
+    pub damage: u16,
+}
+
+impl AttackResult {
+    /// The rolls behind the result, for the verbose log: "d20 14+5=19 vs 13, 1d8 6+2 = 8".
+    pub fn details(&self, attack: &Attack) -> String {
+        let to_hit = format!(
+            "d20 {}{:+}={} vs {}",
+            self.attack_roll, attack.attack_bonus, self.attack_total, self.target
+        );
+        match self.outcome {
+            AttackOutcome::Fumble | AttackOutcome::Miss => to_hit,
+            AttackOutcome::Hit | AttackOutcome::Critical => {
+                let dice = match self.outcome {
+                    AttackOutcome::Critical => Dice::new(2 * attack.dice.count, attack.dice.sides),
+                    _ => attack.dice,
+                };
+                format!(
+                    "{}, {} {}{:+} = {}",
+                    to_hit, dice, self.dice_roll, attack.damage_bonus, self.damage
+                )
+            }
+        }
+    }
+}
+
+/// Rolls to hit and, on a hit, for damage, which is always at least 1.
+pub fn resolve_attack(attack: &Attack, roll_die: &mut impl FnMut(u16) -> u16) -> AttackResult {
+    let attack_roll = roll_die(ATTACK_DIE);
+    let attack_total = attack_roll as i16 + attack.attack_bonus;
+    let target = BASE_DEFENSE + attack.defense;
+    let outcome = match attack_roll {
+        1 => AttackOutcome::Fumble,
+        ATTACK_DIE => AttackOutcome::Critical,
+        _ if attack_total >= target => AttackOutcome::Hit,
+        _ => AttackOutcome::Miss,
+    };
+    let dice_roll = match outcome {
+        AttackOutcome::Fumble | AttackOutcome::Miss => 0,
+        AttackOutcome::Hit => attack.dice.roll(roll_die),
+        AttackOutcome::Critical => attack.dice.roll(roll_die) + attack.dice.roll(roll_die),
+    };
+    let damage = match outcome {
+        AttackOutcome::Fumble | AttackOutcome::Miss => 0,
+        AttackOutcome::Hit | AttackOutcome::Critical => {
+            (dice_roll as i16 + attack.damage_bonus).max(1) as u16
+        }
+    };
+    AttackResult {
+        outcome,
+        attack_roll,
+        attack_total,
+        target,
+        dice_roll,
+        damage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gives back `rolls` in order, checking each fits the die it is for.
+    fn scripted(rolls: &[u16]) -> impl FnMut(u16) -> u16 + '_ {
+        let mut rolls = rolls.iter();
+        move |sides| {
+            let roll = *rolls.next().expect("ran out of scripted rolls");
+            assert!(
+                (1..=sides).contains(&roll),
+                "{} is not a d{} roll",
+                roll,
+                sides
+            );
+            roll
+        }
+    }
+
+    const ATTACK: Attack = Attack {
+        attack_bonus: 5,
+        defense: 3,
+        dice: Dice::new(1, 8),
+        damage_bonus: -2,
+    };
+
+    #[test]
+    fn hits_need_to_reach_the_target() {
+        let miss = resolve_attack(&ATTACK, &mut scripted(&[7]));
+        assert_eq!(miss.outcome, AttackOutcome::Miss);
+        assert_eq!((miss.attack_total, miss.target, miss.damage), (12, 13, 0));
+
+        let hit = resolve_attack(&ATTACK, &mut scripted(&[8, 6]));
+        assert_eq!(hit.outcome, AttackOutcome::Hit);
+        assert_eq!(hit.damage, 4);
+        assert_eq!(hit.details(&ATTACK), "d20 8+5=13 vs 13, 1d8 6-2 = 4");
+    }
+
+    #[test]
+    fn natural_rolls_override_the_target() {
+        let defended = Attack {
+            defense: 40,
+            ..ATTACK
+        };
+        let critical = resolve_attack(&defended, &mut scripted(&[20, 3, 5]));
+        assert_eq!(critical.outcome, AttackOutcome::Critical);
+        assert_eq!(critical.damage, 6);
+
+        let exposed = Attack {
+            defense: -40,
+            ..ATTACK
+        };
+        let fumble = resolve_attack(&exposed, &mut scripted(&[1]));
+        assert_eq!(fumble.outcome, AttackOutcome::Fumble);
+        assert_eq!(fumble.damage, 0);
+    }
+
+    #[test]
+    fn hits_do_at_least_one_damage() {
+        let feeble = Attack {
+            damage_bonus: -10,
+            ..ATTACK
+        };
+        assert_eq!(resolve_attack(&feeble, &mut scripted(&[15, 1])).damage, 1);
+    }
+}
@@ -1,5 +1,6 @@
 use bracket_lib::{
     prelude::{FontCharType, RGB},
+    random::RandomNumberGenerator,
     terminal::Point,
 };
 use specs::{
@@ -9,8 +10,11 @@ use specs::{
 };
 
 use crate::{
-    equipment::{EntityEquipmentMap, EquipSlot, EquipSlotAllowed, Equipment},
+    dungeon::MasterDungeonMap,
+    equipment::{EntityEquipmentMap, EquipSlot, EquipSlotAllowed, Equipment, RangedWeaponType},
+    gamelog::GameLog,
     map::Map,
+    run_info::RunInfo,
     PsnU,
 };
 use serde::{Deserialize, Serialize};
@@ -29,6 +33,26 @@ pub struct AreaOfEffect {
 #[derive(Component, Deserialize, Serialize, Clone, Debug)]
 pub struct BlocksTile {}
 
+/// The player's character level and progress toward the next one.
+#[derive(Component, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Experience {
+    pub level: u16,
+    /// XP earned since reaching `level`
+    pub xp: u32,
+    /// Levels gained but not yet spent on the level-up screen
+    pub pending_level_ups: u16,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Experience {
+            level: 1,
+            xp: 0,
+            pending_level_ups: 0,
+        }
+    }
+}
+
 #[derive(Component, ConvertSaveload, Clone, Debug)]
 pub struct CombatStats {
     pub max_hp: u16,
@@ -37,9 +61,61 @@ pub struct CombatStats {
     pub power: u16,
 }
 
-#[derive(Component, ConvertSaveload, Debug, Clone)]
-pub struct Confusion {
-    pub step_sequence: Vec<(i8, i8)>,
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusKind {
+    Poison,
+    Regeneration,
+    Haste,
+    Slow,
+    Blind,
+    Confusion,
+}
+
+/// A status effect that lasts `turns_left` more turns; `magnitude` is the damage or
+/// healing per turn for poison and regeneration.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub turns_left: u16,
+    pub magnitude: u16,
+}
+
+/// Gains `speed` energy each tick and takes a turn whenever it has enough saved up.
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct Initiative {
+    pub speed: i32,
+    pub energy: i32,
+}
+
+/// Set by the scheduler on everyone whose turn it is now. It only lasts the tick, so it
+/// is left out of saves.
+#[derive(Component, Clone, Debug)]
+pub struct MyTurn {}
+
+/// Every status effect on an entity, at most one of each kind.
+#[derive(Component, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+}
+
+/// Using the item puts `effect` on its targets.
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct InflictsStatus {
+    pub effect: StatusEffect,
+}
+
+/// A stack of arrows, bolts or throwing knives.
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct Ammunition {
+    pub kind: RangedWeaponType,
+    pub count: u16,
+}
+
+impl Ammunition {
+    /// Stacks are named with their size, so this is their `Name` whenever `count` changes.
+    pub fn name(&self) -> String {
+        format!("{} ({})", self.kind.ammo_name(), self.count)
+    }
 }
 
 #[derive(Component, Deserialize, Serialize, Clone, Debug)]
@@ -172,6 +248,11 @@ pub struct EventWantsToMelee {
     pub target: Entity,
 }
 
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct EventWantsToShoot {
+    pub target: Position,
+}
+
 #[derive(Component, ConvertSaveload, Clone, Debug)]
 pub struct EventWantsToPickupItem {
     pub collected_by: Entity,
@@ -183,6 +264,26 @@ pub struct EventWantsToRemoveItem {
     pub item: Entity,
 }
 
+/// Who last damaged this entity, to credit its death to them.
+#[derive(Component, ConvertSaveload, Clone, Debug)]
+pub struct LastHitBy {
+    pub attacker: Entity,
+}
+
+impl LastHitBy {
+    pub fn record(store: &mut WriteStorage<LastHitBy>, victim: Entity, attacker: Entity) {
+        store
+            .insert(victim, LastHitBy { attacker })
+            .expect("Unable to record attacker");
+    }
+}
+
+/// An effect its caster can use at will, like an item that is never used up.
+#[derive(Clone, Component, ConvertSaveload, Debug)]
+pub struct Spell {
+    pub caster: Entity,
+}
+
 #[derive(Clone, Component, ConvertSaveload, Debug)]
 pub struct InBackpack {
     pub owner: Entity,
@@ -216,6 +317,7 @@ pub struct InflictsDamage {
 pub enum Item {
     Consumable,
     Equippable(Equipment), // Note: In book this is a component
+    Ammunition,
 }
 
 impl Item {
@@ -249,6 +351,57 @@ impl IsItem for Item {
 #[derive(Component, Deserialize, Serialize, Clone, Debug)]
 pub struct Monster {}
 
+/// Rolled on when a monster dies: `chance` percent of the time, the raws spawn table
+/// `table` drops something where it fell.
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct LootTable {
+    pub table: String,
+    pub chance: u8,
+}
+
+/// Marks a monster that leaves a `Corpse` where it dies.
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct LeavesCorpse {}
+
+/// Left where a monster died.
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct Corpse {}
+
+/// What a monster is up to while the player is out of its sight.
+#[derive(Component, ConvertSaveload, Clone, Copy, Debug, PartialEq)]
+pub enum AiState {
+    /// Waits where it is, setting off to wander now and then
+    Idle,
+    /// Heads for a room elsewhere on the level
+    Wandering(Position),
+    /// Hunts its quarry from where it was last seen, for so many turns out of sight
+    Chasing(Position, u16),
+    /// Runs from its foes until its courage returns
+    Fleeing,
+}
+
+/// A monster's nerve out of `base`; together with its HP it decides when the monster flees.
+#[derive(Component, Deserialize, Serialize, Clone, Debug)]
+pub struct Morale {
+    pub base: i32,
+    pub current: i32,
+}
+
+/// Which side an entity is on; the raws say how each faction reacts to the others.
+#[derive(Component, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Faction {
+    pub name: String,
+}
+
+/// What kind of creature a monster is; some weapon materials fare better or worse against it.
+#[derive(Component, Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Creature {
+    Undead,
+    Beast,
+    Construct,
+    Demon,
+}
+
 #[derive(Component, ConvertSaveload, Clone, Debug)]
 pub struct Name {
     pub name: String,
@@ -291,6 +444,13 @@ pub struct Position {
     pub yy: PsnU,
 }
 
+/// Where an entity is on a level the player isn't on; it has no `Position` meanwhile.
+#[derive(Component, Clone, Copy, ConvertSaveload, Debug)]
+pub struct OtherLevelPosition {
+    pub depth: i32,
+    pub pos: Position,
+}
+
 impl From<Position> for Point {
     fn from(pos: Position) -> Self {
         Point::new(pos.xx, pos.yy)
@@ -361,6 +521,8 @@ pub struct Range {
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Deserialize, Serialize, Debug)]
 pub enum RenderOrder {
+    /// Beneath even items, such as corpses
+    Floor,
     First,
     Second,
     Last,
@@ -377,6 +539,10 @@ pub struct Renderable {
 #[derive(Component, ConvertSaveload, Clone)]
 pub struct SerializationHelper {
     pub map: Map,
+    pub run_info: RunInfo,
+    pub rng: RandomNumberGenerator,
+    pub log: GameLog,
+    pub dungeon: MasterDungeonMap,
 }
 
 pub struct SerializeMe;
@@ -402,29 +568,44 @@ macro_rules! execute_with_type_list {
     ($name:ident!($($arg:tt)*)) => {
         $name!(
           $($arg)*,
+          AiState,
+          Ammunition,
           AreaOfEffect,
           BlocksTile,
           CombatStats,
-          Confusion,
           Consumable,
+          Corpse,
+          Creature,
           Equipped,
           EventIncomingDamage,
           EventWantsToDropItem,
           EventWantsToMelee,
           EventWantsToPickupItem,
           EventWantsToRemoveItem,
+          EventWantsToShoot,
           EventWantsToUseItem,
+          Experience,
+          Faction,
           InBackpack,
           InflictsDamage,
+          InflictsStatus,
+          Initiative,
           Item,
+          LastHitBy,
+          LeavesCorpse,
+          LootTable,
           Monster,
+          Morale,
           Name,
+          OtherLevelPosition,
           Player,
           Position,
           ProvidesHealing,
           Range,
           Renderable,
           SerializationHelper,
+          Spell,
+          StatusEffects,
           Viewshed,
           WantsToUnequipItem,
         )
@@ -1,9 +1,12 @@
 use crate::{
-    components::{Name, Player, Position, Positionable},
+    components::{
+        Equipped, InBackpack, LastHitBy, Monster, Name, Player, Position, Positionable, Spell,
+    },
     entity_action_msg,
+    experience::{award_xp, kill_xp},
     gamelog::GameLog,
     map::Map,
-    RunState,
+    morale, spawner, RunState,
 };
 
 use super::{CombatStats, EventIncomingDamage};
@@ -29,8 +32,33 @@ impl<'a> System<'a> for DamageSystem {
     }
 }
 
+/// Everything `owner` carried or wore falls to the floor at `pos`.
+fn drop_belongings(ecs: &World, owner: Entity, pos: Position) {
+    let entities = ecs.entities();
+    let mut backpack = ecs.write_storage::<InBackpack>();
+    let mut equipped = ecs.write_storage::<Equipped>();
+    let mut positions = ecs.write_storage::<Position>();
+    let belongings: Vec<Entity> = (&entities, backpack.maybe(), equipped.maybe())
+        .join()
+        .filter(|(_, pack, worn)| {
+            pack.map_or(false, |pack| pack.owner == owner)
+                || worn.map_or(false, |worn| worn.owner == owner)
+        })
+        .map(|(item, _, _)| item)
+        .collect();
+    belongings.into_iter().for_each(|item| {
+        backpack.remove(item);
+        equipped.remove(item);
+        positions
+            .insert(item, pos)
+            .unwrap_or_else(|er| panic!("Unable to drop item {}: {}", item.id(), er));
+    });
+}
+
 pub fn delete_the_dead(ecs: &mut World) -> Option<RunState> {
     let mut dead: Vec<Entity> = Vec::new();
+    let mut kill_credits: Vec<(Entity, u32)> = Vec::new();
+    let mut monster_deaths: Vec<(Entity, Position)> = Vec::new();
     let mut newrunstate_opt = None;
     {
         let entities = ecs.entities();
@@ -38,6 +66,9 @@ pub fn delete_the_dead(ecs: &mut World) -> Option<RunState> {
         let combat_stats = ecs.read_storage::<CombatStats>();
         let positions = ecs.read_storage::<Position>();
         let players = ecs.read_storage::<Player>();
+        let last_hit_by = ecs.read_storage::<LastHitBy>();
+        let monsters = ecs.read_storage::<Monster>();
+        let spells = ecs.read_storage::<Spell>();
         (&entities, &combat_stats, &positions)
             .join()
             .for_each(|(ent, stats, pos)| {
@@ -50,6 +81,13 @@ pub fn delete_the_dead(ecs: &mut World) -> Option<RunState> {
                         {
                             newrunstate_opt = Some(RunState::GameOver);
                         }
+                    } else if let Some(hit) = last_hit_by.get(ent) {
+                        if players.get(hit.attacker).is_some() {
+                            kill_credits.push((hit.attacker, kill_xp(stats)));
+                        }
+                    }
+                    if monsters.get(ent).is_some() {
+                        monster_deaths.push((ent, *pos));
                     }
                     let ix = {
                         let map = ecs.fetch::<Map>();
@@ -59,10 +97,127 @@ pub fn delete_the_dead(ecs: &mut World) -> Option<RunState> {
                     map.blocked[ix] = false;
                 }
             });
+        // Spells die with their caster
+        let forgotten: Vec<Entity> = (&entities, &spells)
+            .join()
+            .filter(|(_, spell)| dead.contains(&spell.caster))
+            .map(|(spell_ent, _)| spell_ent)
+            .collect();
+        dead.extend(forgotten);
     }
+    monster_deaths.into_iter().for_each(|(monster, pos)| {
+        drop_belongings(ecs, monster, pos);
+        spawner::drop_loot(ecs, monster, pos);
+        spawner::corpse(ecs, monster, pos);
+        morale::ally_died(ecs, monster, pos);
+    });
+    kill_credits.iter().for_each(|(killer, xp)| {
+        if let Some(level) = award_xp(ecs, *killer, *xp) {
+            ecs.write_resource::<GameLog>().entries.push(format!(
+                "You feel more experienced: welcome to level {}!",
+                level
+            ));
+        }
+    });
     dead.iter().for_each(|victim| {
         ecs.delete_entity(*victim)
             .unwrap_or_else(|er| panic!("Unable to delete entity with id {}: {}", victim.id(), er))
     });
     newrunstate_opt
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        components::{Corpse, Item, LootTable},
+        init_state,
+        player::{get_player_pos_unwrap, PLAYER_NAME},
+        spawner,
+    };
+
+    use super::*;
+
+    #[test]
+    fn dead_monsters_drop_their_gear() {
+        let (mut gs, _) = init_state(true, None);
+        let player_posn = get_player_pos_unwrap(&gs.ecs, PLAYER_NAME);
+        let archer = spawner::spawn_named(&mut gs.ecs, "Goblin Archer", player_posn, 1).unwrap();
+        let gear: Vec<Entity> = {
+            let entities = gs.ecs.entities();
+            let backpack = gs.ecs.read_storage::<InBackpack>();
+            let equipped = gs.ecs.read_storage::<Equipped>();
+            (&entities, &backpack)
+                .join()
+                .filter(|(_, pack)| pack.owner == archer)
+                .map(|(item, _)| item)
+                .chain(
+                    (&entities, &equipped)
+                        .join()
+                        .filter(|(_, worn)| worn.owner == archer)
+                        .map(|(item, _)| item),
+                )
+                .collect()
+        };
+        // Its bow and its arrows
+        assert_eq!(gear.len(), 2);
+
+        gs.ecs
+            .write_storage::<CombatStats>()
+            .get_mut(archer)
+            .unwrap()
+            .hp = 0;
+        delete_the_dead(&mut gs.ecs);
+        gs.ecs.maintain();
+
+        assert!(!gs.ecs.is_alive(archer));
+        let positions = gs.ecs.read_storage::<Position>();
+        assert!(gear
+            .iter()
+            .all(|item| positions.get(*item) == Some(&player_posn)));
+    }
+
+    #[test]
+    fn dead_monsters_leave_loot_and_a_corpse() {
+        let (mut gs, _) = init_state(true, None);
+        let player_posn = get_player_pos_unwrap(&gs.ecs, PLAYER_NAME);
+        let shaman = spawner::spawn_named(&mut gs.ecs, "Goblin Shaman", player_posn, 1).unwrap();
+        gs.ecs
+            .write_storage::<LootTable>()
+            .insert(
+                shaman,
+                LootTable {
+                    table: "shaman loot".to_string(),
+                    chance: 100,
+                },
+            )
+            .unwrap();
+        let items_here = |ecs: &World| {
+            let items = ecs.read_storage::<Item>();
+            let positions = ecs.read_storage::<Position>();
+            (&items, &positions)
+                .join()
+                .filter(|(_, pos)| **pos == player_posn)
+                .count()
+        };
+        let items_before = items_here(&gs.ecs);
+
+        gs.ecs
+            .write_storage::<CombatStats>()
+            .get_mut(shaman)
+            .unwrap()
+            .hp = 0;
+        delete_the_dead(&mut gs.ecs);
+        gs.ecs.maintain();
+
+        assert_eq!(items_here(&gs.ecs), items_before + 1);
+        let corpses = gs.ecs.read_storage::<Corpse>();
+        let names = gs.ecs.read_storage::<Name>();
+        let positions = gs.ecs.read_storage::<Position>();
+        let corpse_names: Vec<&str> = (&corpses, &names, &positions)
+            .join()
+            .filter(|(_, _, pos)| **pos == player_posn)
+            .map(|(_, name, _)| name.name.as_str())
+            .collect();
+        assert_eq!(corpse_names, vec!["Goblin Shaman corpse"]);
+    }
+}
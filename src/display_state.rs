@@ -6,6 +6,8 @@ use crate::PsnU;
 pub struct DisplayState {
     pub width: PsnU,
     pub height: PsnU,
+    /// Debug view of what each monster's AI is up to
+    pub ai_overlay: bool,
 }
 
 impl DisplayState {
@@ -19,6 +21,7 @@ impl DisplayState {
         DisplayState {
             width: 80,
             height: 50,
+            ai_overlay: false,
         }
     }
 }
@@ -28,5 +31,6 @@ pub fn calc_display_state(ctxt: &BTerm) -> DisplayState {
     DisplayState {
         width: ctxt_char_size.0.try_into().unwrap(),
         height: ctxt_char_size.1.try_into().unwrap(),
+        ai_overlay: false,
     }
 }
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+use crate::{
+    components::{OtherLevelPosition, Player, Position, Viewshed},
+    map::Map,
+};
+
+/// Every level the player has left, by depth, as it was when they left it.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct MasterDungeonMap {
+    maps: BTreeMap<i32, Map>,
+}
+
+impl MasterDungeonMap {
+    pub fn store_map(&mut self, map: &Map) {
+        self.maps.insert(map.depth, map.clone());
+    }
+
+    pub fn get_map(&self, depth: i32) -> Option<Map> {
+        self.maps.get(&depth).map(|map| {
+            let mut map = map.clone();
+            map.tile_content = vec![Vec::new(); map.tile_count()];
+            map
+        })
+    }
+}
+
+/// Takes everything but the player off the current level, remembering where it was.
+/// Items the player carries have no `Position`, so they come along.
+pub fn freeze_level_entities(ecs: &World) {
+    let depth = ecs.fetch::<Map>().depth;
+    let entities = ecs.entities();
+    let players = ecs.read_storage::<Player>();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+
+    let to_freeze: Vec<(Entity, Position)> = (&entities, &positions, !&players)
+        .join()
+        .map(|(ent, pos, _)| (ent, *pos))
+        .collect();
+    to_freeze.into_iter().for_each(|(ent, pos)| {
+        positions.remove(ent);
+        other_level_positions
+            .insert(ent, OtherLevelPosition { depth, pos })
+            .unwrap_or_else(|er| panic!("Unable to freeze entity {}: {}", ent.id(), er));
+    });
+}
+
+/// Puts everything left on level `depth` back where it was.
+pub fn thaw_level_entities(ecs: &World, depth: i32) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+
+    let to_thaw: Vec<(Entity, Position)> = (&entities, &other_level_positions)
+        .join()
+        .filter(|(_, other)| other.depth == depth)
+        .map(|(ent, other)| (ent, other.pos))
+        .collect();
+    to_thaw.into_iter().for_each(|(ent, pos)| {
+        other_level_positions.remove(ent);
+        positions
+            .insert(ent, pos)
+            .unwrap_or_else(|er| panic!("Unable to thaw entity {}: {}", ent.id(), er));
+        if let Some(viewshed) = viewsheds.get_mut(ent) {
+            viewshed.dirty = true;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init_state;
+
+    fn positioned(ecs: &World) -> Vec<(Entity, Position)> {
+        (&ecs.entities(), &ecs.read_storage::<Position>())
+            .join()
+            .map(|(ent, pos)| (ent, *pos))
+            .collect()
+    }
+
+    #[test]
+    fn thawed_level_is_as_it_was_left() {
+        let (gs, _) = init_state(true, None);
+        let before = positioned(&gs.ecs);
+
+        freeze_level_entities(&gs.ecs);
+        assert_eq!(positioned(&gs.ecs).len(), 1);
+        thaw_level_entities(&gs.ecs, 2);
+        assert_eq!(positioned(&gs.ecs).len(), 1);
+
+        thaw_level_entities(&gs.ecs, 1);
+        assert_eq!(positioned(&gs.ecs), before);
+    }
+}
@@ -5,11 +5,12 @@ use specs::{
     saveload::{ConvertSaveload, Marker},
 };
 
+use itertools::Itertools;
 use specs_derive::*;
 
 use std::{collections::HashMap, convert::Infallible, fmt::Display};
 
-use crate::components::*;
+use crate::{combat::Dice, components::*};
 // `NoError` alias is deprecated in specs ... but specs_derive needs it
 pub type NoError = Infallible;
 
@@ -92,6 +93,29 @@ impl MeleeWeaponType {
             MeleeWeaponType::Whip => 1,
         }
     }
+
+    /// Daggers are quick but light; axes swing between glancing blows and cleaving ones.
+    pub fn dice(&self) -> Dice {
+        match self {
+            MeleeWeaponType::Axe => Dice::new(1, 12),
+            MeleeWeaponType::Mace => Dice::new(2, 4),
+            MeleeWeaponType::Sword => Dice::new(1, 8),
+            MeleeWeaponType::Dagger => Dice::new(1, 4),
+            MeleeWeaponType::Staff => Dice::new(1, 6),
+            MeleeWeaponType::Polearm => Dice::new(1, 10),
+            MeleeWeaponType::Whip => Dice::new(1, 4),
+        }
+    }
+
+    /// Added to the attack roll.
+    pub fn accuracy(&self) -> i16 {
+        match self {
+            MeleeWeaponType::Dagger => 2,
+            MeleeWeaponType::Whip => 1,
+            MeleeWeaponType::Axe => -1,
+            _ => 0,
+        }
+    }
 }
 
 #[derive(Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Debug)]
@@ -103,12 +127,25 @@ pub enum Infix {
     Long,
     Bastard,
     Great,
+    Battle,
     //
     Buckler,
     Round,
     Heater,
     Kite,
     Tower,
+    //
+    Padded,
+    Studded,
+    Scale,
+    Chain,
+    Plate,
+    //
+    Plain,
+    Engraved,
+    Jeweled,
+    Runed,
+    Royal,
 }
 impl Display for Infix {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -120,14 +157,145 @@ impl Display for Infix {
             Infix::Long => write!(f, "Long"),
             Infix::Bastard => write!(f, "Bastard"),
             Infix::Great => write!(f, "Great"),
+            Infix::Battle => write!(f, "Battle"),
             //
             Infix::Buckler => write!(f, "Buckler"),
             Infix::Round => write!(f, "Round"),
             Infix::Heater => write!(f, "Heater"),
             Infix::Kite => write!(f, "Kite"),
             Infix::Tower => write!(f, "Tower"),
+            //
+            Infix::Padded => write!(f, "Padded"),
+            Infix::Studded => write!(f, "Studded"),
+            Infix::Scale => write!(f, "Scale"),
+            Infix::Chain => write!(f, "Chain"),
+            Infix::Plate => write!(f, "Plate"),
+            //
+            Infix::Plain => write!(f, "Plain"),
+            Infix::Engraved => write!(f, "Engraved"),
+            Infix::Jeweled => write!(f, "Jeweled"),
+            Infix::Runed => write!(f, "Runed"),
+            Infix::Royal => write!(f, "Royal"),
+        }
+    }
+}
+
+/// A rolled extra on a piece of equipment: a prefix ("Vicious Iron Sword")
+/// or a suffix ("Iron Helmet of Farsight").
+#[derive(Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum Affix {
+    // Prefixes
+    Keen,
+    Vicious,
+    Sturdy,
+    Vampiric,
+    // Suffixes
+    Might,
+    Warding,
+    Farsight,
+}
+
+pub const AFFIXES: [Affix; 7] = [
+    Affix::Keen,
+    Affix::Vicious,
+    Affix::Sturdy,
+    Affix::Vampiric,
+    Affix::Might,
+    Affix::Warding,
+    Affix::Farsight,
+];
+
+/// Weight of rolling no prefix (or no suffix) at all, against `Affix::weight_at`.
+pub const NO_AFFIX_WEIGHT: u16 = 100;
+
+impl Display for Affix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Affix::Keen => write!(f, "Keen"),
+            Affix::Vicious => write!(f, "Vicious"),
+            Affix::Sturdy => write!(f, "Sturdy"),
+            Affix::Vampiric => write!(f, "Vampiric"),
+            Affix::Might => write!(f, "of Might"),
+            Affix::Warding => write!(f, "of Warding"),
+            Affix::Farsight => write!(f, "of Farsight"),
+        }
+    }
+}
+
+impl Affix {
+    pub fn is_prefix(&self) -> bool {
+        matches!(
+            self,
+            Affix::Keen | Affix::Vicious | Affix::Sturdy | Affix::Vampiric
+        )
+    }
+
+    pub fn power_modifier(&self) -> i16 {
+        match self {
+            Affix::Keen | Affix::Might => 1,
+            Affix::Vicious => 2,
+            _ => 0,
         }
     }
+
+    pub fn defense_modifier(&self) -> i16 {
+        match self {
+            Affix::Sturdy => 1,
+            Affix::Warding => 2,
+            _ => 0,
+        }
+    }
+
+    /// Percent of melee damage dealt that heals the wielder.
+    pub fn lifesteal_percent(&self) -> u16 {
+        match self {
+            Affix::Vampiric => 25,
+            _ => 0,
+        }
+    }
+
+    pub fn view_range_bonus(&self) -> i32 {
+        match self {
+            Affix::Farsight => 2,
+            _ => 0,
+        }
+    }
+
+    /// Whether the affix makes sense on `equipment`: no vicious boots.
+    pub fn fits(&self, equipment: &Equipment) -> bool {
+        let boosts_power = matches!(equipment.equipment_type, EquipmentType::Weapon(_))
+            || matches!(
+                (&equipment.equipment_type, equipment.primary_slot()),
+                (
+                    EquipmentType::Accessory,
+                    EquipSlot::Ring | EquipSlot::SecondRing
+                )
+            );
+        match self {
+            Affix::Keen | Affix::Vicious | Affix::Might => boosts_power,
+            Affix::Vampiric => matches!(
+                equipment.equipment_type,
+                EquipmentType::Weapon(WeaponType::Melee(_))
+            ),
+            Affix::Sturdy | Affix::Warding => !boosts_power,
+            Affix::Farsight => matches!(
+                (&equipment.equipment_type, equipment.primary_slot()),
+                (EquipmentType::Armor, EquipSlot::Head)
+                    | (EquipmentType::Accessory, EquipSlot::Neck)
+            ),
+        }
+    }
+
+    /// Stronger affixes are rarer, and all grow more common with depth.
+    pub fn weight_at(&self, depth: i32) -> u16 {
+        let base: i32 = match self {
+            Affix::Keen | Affix::Sturdy | Affix::Might => 8,
+            Affix::Warding | Affix::Farsight => 5,
+            Affix::Vicious => 3,
+            Affix::Vampiric => 2,
+        };
+        (base + depth).clamp(0, i32::from(u16::MAX)) as u16
+    }
 }
 
 macro_attr! {
@@ -150,8 +318,8 @@ macro_attr! {
 }
 
 impl Material {
-    // Note: We may want to override these bonuses for specific equipment types
-    // or for specific interactions (e.g. silver vs. undead)
+    // Note: We may want to override these bonuses for specific equipment types;
+    // interactions with creatures (e.g. silver vs. undead) are in `against`
     pub fn bonus(&self) -> i16 {
         match self {
             Material::Wood => 0,
@@ -170,6 +338,22 @@ impl Material {
         }
     }
 
+    /// How a weapon of this material fares against `creature`.
+    pub fn against(&self, creature: Creature) -> MaterialEffect {
+        match (self, creature) {
+            (Material::Silver, Creature::Undead) => MaterialEffect::Modified(4),
+            (Material::Silver, Creature::Demon) => MaterialEffect::Modified(3),
+            (Material::Wood, Creature::Undead) => MaterialEffect::Modified(2),
+            (Material::Iron, Creature::Demon) => MaterialEffect::Modified(2),
+            (Material::Wood, Creature::Construct) => MaterialEffect::Useless,
+            (Material::Stone | Material::Copper | Material::Tin, Creature::Construct) => {
+                MaterialEffect::Modified(-2)
+            }
+            (Material::Wood | Material::Stone, Creature::Beast) => MaterialEffect::Modified(-1),
+            _ => MaterialEffect::Normal,
+        }
+    }
+
     pub fn color(&self) -> (u8, u8, u8) {
         match self {
             Material::Wood => (102, 51, 0),
@@ -189,6 +373,16 @@ impl Material {
     }
 }
 
+/// A weapon material's effect on damage against some kind of creature.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MaterialEffect {
+    Normal,
+    /// Extra damage, or less when negative
+    Modified(i16),
+    /// Does no harm at all
+    Useless,
+}
+
 macro_attr! {
 #[derive(Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Debug, EnumDisplay!)]
     pub enum RangedWeaponType {
@@ -206,6 +400,15 @@ impl RangedWeaponType {
             RangedWeaponType::Thrown => 0,
         }
     }
+
+    /// What this kind of weapon fires.
+    pub fn ammo_name(&self) -> &'static str {
+        match self {
+            RangedWeaponType::Bow => "Arrows",
+            RangedWeaponType::Crossbow => "Bolts",
+            RangedWeaponType::Thrown => "Throwing Knives",
+        }
+    }
 }
 
 macro_attr! {
@@ -219,6 +422,7 @@ macro_attr! {
         Feet,
         MainHand,
         OffHand,
+        SecondRing,
     }
 }
 
@@ -235,6 +439,9 @@ pub const ONE_HANDED: EquipSlotAllowed =
     EquipSlotAllowed::Either(EquipSlot::MainHand, EquipSlot::OffHand);
 pub const OFF_HAND: EquipSlotAllowed = EquipSlotAllowed::SingleSlot(EquipSlot::OffHand);
 
+/// Extra power for giving up the off hand.
+const TWO_HANDED_BONUS: i16 = 2;
+
 #[derive(Eq, PartialEq, Hash, Component, ConvertSaveload, Clone, Debug)]
 pub struct Equipment {
     pub equipment_type: EquipmentType,
@@ -243,6 +450,8 @@ pub struct Equipment {
     pub quality: u8,
     pub special_power_modifier: i16,
     pub special_defense_modifier: i16,
+    pub prefix: Option<Affix>,
+    pub suffix: Option<Affix>,
 }
 
 impl Equipment {
@@ -259,9 +468,50 @@ impl Equipment {
             quality,
             special_power_modifier: 0,
             special_defense_modifier: 0,
+            prefix: None,
+            suffix: None,
+        }
+    }
+
+    /// Adds `affix` as the prefix or suffix, replacing any there, along with its modifiers.
+    pub fn with_affix(self, affix: Affix) -> Self {
+        let replaced = if affix.is_prefix() {
+            self.prefix
+        } else {
+            self.suffix
+        };
+        let (old_power, old_defense) =
+            replaced.map_or((0, 0), |old| (old.power_modifier(), old.defense_modifier()));
+        let special_power_modifier =
+            self.special_power_modifier - old_power + affix.power_modifier();
+        let special_defense_modifier =
+            self.special_defense_modifier - old_defense + affix.defense_modifier();
+        let (prefix, suffix) = if affix.is_prefix() {
+            (Some(affix), self.suffix)
+        } else {
+            (self.prefix, Some(affix))
+        };
+        Equipment {
+            special_power_modifier,
+            special_defense_modifier,
+            prefix,
+            suffix,
+            ..self
         }
     }
 
+    fn affixes(&self) -> impl Iterator<Item = Affix> {
+        self.prefix.into_iter().chain(self.suffix)
+    }
+
+    pub fn lifesteal_percent(&self) -> u16 {
+        self.affixes().map(|affix| affix.lifesteal_percent()).sum()
+    }
+
+    pub fn view_range_bonus(&self) -> i32 {
+        self.affixes().map(|affix| affix.view_range_bonus()).sum()
+    }
+
     fn sword_infix(quality: u8) -> Infix {
         match quality {
             0 => Infix::Short,
@@ -282,17 +532,63 @@ impl Equipment {
         }
     }
 
+    fn armor_infix(quality: u8) -> Infix {
+        match quality {
+            0 => Infix::Padded,
+            1 => Infix::Studded,
+            2 => Infix::Scale,
+            3 => Infix::Chain,
+            _ => Infix::Plate,
+        }
+    }
+
+    fn accessory_infix(quality: u8) -> Infix {
+        match quality {
+            0 => Infix::Plain,
+            1 => Infix::Engraved,
+            2 => Infix::Jeweled,
+            3 => Infix::Runed,
+            _ => Infix::Royal,
+        }
+    }
+
     fn infix(&self) -> Infix {
         match &self.equipment_type {
             EquipmentType::Weapon(weapon_type) => match weapon_type {
                 WeaponType::Melee(melee_weapon_type) => match melee_weapon_type {
+                    MeleeWeaponType::Sword if self.is_2h() => Infix::Great,
                     MeleeWeaponType::Sword => Self::sword_infix(self.quality),
+                    MeleeWeaponType::Axe if self.is_2h() => Infix::Battle,
                     _ => Infix::None,
                 },
                 WeaponType::Ranged(_, _) => Infix::None,
             },
             EquipmentType::Shield => Self::shield_infix(self.quality),
-            _ => Infix::None,
+            EquipmentType::Armor => Self::armor_infix(self.quality),
+            EquipmentType::Accessory => Self::accessory_infix(self.quality),
+        }
+    }
+
+    /// The slot an item goes in first; armor and accessories are told apart by it.
+    pub fn primary_slot(&self) -> &EquipSlot {
+        match &self.allowed_slots {
+            EquipSlotAllowed::SingleSlot(slot)
+            | EquipSlotAllowed::Either(slot, _)
+            | EquipSlotAllowed::Both(slot, _) => slot,
+        }
+    }
+
+    /// The last word of the name: "Sword", "Shield", "Helmet", "Ring", ...
+    fn noun(&self) -> String {
+        match (&self.equipment_type, self.primary_slot()) {
+            (EquipmentType::Armor, EquipSlot::Head) => "Helmet".to_string(),
+            (EquipmentType::Armor, EquipSlot::Hand) => "Gauntlets".to_string(),
+            (EquipmentType::Armor, EquipSlot::Feet) => "Boots".to_string(),
+            (EquipmentType::Accessory, EquipSlot::Neck) => "Amulet".to_string(),
+            (EquipmentType::Accessory, EquipSlot::Ring | EquipSlot::SecondRing) => {
+                "Ring".to_string()
+            }
+            (equipment_type, _) => equipment_type.to_string(),
         }
     }
 
@@ -301,27 +597,134 @@ impl Equipment {
             Infix::None => " ".to_string(),
             ifx => format!(" {} ", ifx),
         };
-        format!("{}{}{}", self.material, infix, self.equipment_type)
+        let prefix = self
+            .prefix
+            .map_or_else(String::new, |affix| format!("{} ", affix));
+        let suffix = self
+            .suffix
+            .map_or_else(String::new, |affix| format!(" {}", affix));
+        format!(
+            "{}{}{}{}{}",
+            prefix,
+            self.material,
+            infix,
+            self.noun(),
+            suffix
+        )
+    }
+
+    /// Body armor protects fully; helmets, gauntlets, boots and accessories give half.
+    fn is_minor_piece(&self) -> bool {
+        !matches!(
+            self.primary_slot(),
+            EquipSlot::Torso | EquipSlot::MainHand | EquipSlot::OffHand
+        )
+    }
+
+    fn material_bonus(&self) -> i16 {
+        let bonus = self.equipment_type.bonus() + self.material.bonus();
+        if self.is_minor_piece() {
+            bonus / 2
+        } else {
+            bonus
+        }
     }
 
     pub fn power_bonus(&self) -> i16 {
-        let derived_bonus = match self.equipment_type {
-            EquipmentType::Weapon(_) => self.equipment_type.bonus() + self.material.bonus(),
+        let derived_bonus = match (&self.equipment_type, self.primary_slot()) {
+            (EquipmentType::Weapon(_), _) if self.is_2h() => {
+                self.material_bonus() + TWO_HANDED_BONUS
+            }
+            (EquipmentType::Weapon(_), _) => self.material_bonus(),
+            (EquipmentType::Accessory, EquipSlot::Ring | EquipSlot::SecondRing) => {
+                self.material_bonus()
+            }
             _ => 0,
         };
         derived_bonus + self.special_power_modifier
     }
 
     pub fn defense_bonus(&self) -> i16 {
-        let derived_bonus = match self.equipment_type {
-            EquipmentType::Armor | EquipmentType::Shield => {
-                self.equipment_type.bonus() + self.material.bonus()
-            }
+        let derived_bonus = match (&self.equipment_type, self.primary_slot()) {
+            (EquipmentType::Armor | EquipmentType::Shield, _) => self.material_bonus(),
+            (EquipmentType::Accessory, EquipSlot::Neck) => self.material_bonus(),
             _ => 0,
         };
         derived_bonus + self.special_defense_modifier
     }
 
+    pub fn is_ranged(&self) -> bool {
+        matches!(
+            self.equipment_type,
+            EquipmentType::Weapon(WeaponType::Ranged(_, _))
+        )
+    }
+
+    /// Damage dice of a melee weapon; two-handed ones roll twice as many.
+    pub fn damage_dice(&self) -> Option<Dice> {
+        match &self.equipment_type {
+            EquipmentType::Weapon(WeaponType::Melee(weapon_type)) => {
+                let dice = weapon_type.dice();
+                Some(if self.is_2h() {
+                    Dice::new(2 * dice.count, dice.sides)
+                } else {
+                    dice
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn accuracy(&self) -> i16 {
+        match &self.equipment_type {
+            EquipmentType::Weapon(WeaponType::Melee(weapon_type)) => weapon_type.accuracy(),
+            _ => 0,
+        }
+    }
+
+    /// Speed lost to the weight of chain and plate, tower shields and two-handed weapons.
+    pub fn speed_penalty(&self) -> i32 {
+        let penalty = match (&self.equipment_type, self.infix()) {
+            (EquipmentType::Armor, Infix::Chain) => 5,
+            (EquipmentType::Armor, Infix::Plate) => 10,
+            (EquipmentType::Shield, Infix::Tower) => 10,
+            (EquipmentType::Weapon(_), _) if self.is_2h() => 5,
+            _ => 0,
+        };
+        if self.is_minor_piece() {
+            penalty / 2
+        } else {
+            penalty
+        }
+    }
+
+    /// Power and defense together, to weigh one piece against another.
+    fn worth(&self) -> i16 {
+        self.power_bonus() + self.defense_bonus()
+    }
+
+    /// Whether this is worth more than what it would displace from `worn`.
+    pub fn is_upgrade_over(&self, worn: &EntityEquipmentMap) -> bool {
+        let slots = match &self.allowed_slots {
+            EquipSlotAllowed::SingleSlot(slot) => vec![slot],
+            EquipSlotAllowed::Both(slot1, slot2) => vec![slot1, slot2],
+            // `Equipped::new` only displaces anything once both are taken, from the first
+            EquipSlotAllowed::Either(slot1, slot2)
+                if worn.contains_key(slot1) && worn.contains_key(slot2) =>
+            {
+                vec![slot1]
+            }
+            EquipSlotAllowed::Either(_, _) => vec![],
+        };
+        let displaced: i16 = slots
+            .into_iter()
+            .filter_map(|slot| worn.get(slot))
+            .unique_by(|(_, ent)| *ent)
+            .map(|(equipment, _)| equipment.worth())
+            .sum();
+        self.worth() > displaced
+    }
+
     pub fn is_2h(&self) -> bool {
         self.allowed_slots == TWO_HANDED
     }
@@ -367,3 +770,61 @@ where
 
     equipped_items
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn affixes_name_and_modify_equipment() {
+        let sword = Equipment::new(
+            ONE_HANDED,
+            EquipmentType::Weapon(WeaponType::Melee(MeleeWeaponType::Sword)),
+            Material::Iron,
+            0,
+        );
+        let plain_power = sword.power_bonus();
+        let sword = sword
+            .with_affix(Affix::Keen)
+            .with_affix(Affix::Might)
+            .with_affix(Affix::Vicious);
+        assert_eq!(sword.name(), "Vicious Iron Short Sword of Might");
+        // Vicious replaced Keen, so only its modifier counts
+        assert_eq!(sword.power_bonus(), plain_power + 3);
+    }
+
+    #[test]
+    fn upgrades_weigh_what_they_displace() {
+        let sword = |slots, material| {
+            Equipment::new(
+                slots,
+                EquipmentType::Weapon(WeaponType::Melee(MeleeWeaponType::Sword)),
+                material,
+                0,
+            )
+        };
+        let world = World::new();
+        let mut worn = EntityEquipmentMap::new();
+        worn.insert(
+            EquipSlot::MainHand,
+            (
+                sword(ONE_HANDED, Material::Steel),
+                world.entities().create(),
+            ),
+        );
+        // Anything beats an empty hand
+        assert!(sword(ONE_HANDED, Material::Wood).is_upgrade_over(&worn));
+
+        worn.insert(
+            EquipSlot::OffHand,
+            (
+                sword(ONE_HANDED, Material::Steel),
+                world.entities().create(),
+            ),
+        );
+        assert!(!sword(ONE_HANDED, Material::Iron).is_upgrade_over(&worn));
+        assert!(sword(ONE_HANDED, Material::DamascusSteel).is_upgrade_over(&worn));
+        // Both swords would have to go
+        assert!(!sword(TWO_HANDED, Material::Iron).is_upgrade_over(&worn));
+    }
+}
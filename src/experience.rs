@@ -0,0 +1,114 @@
+use specs::prelude::*;
+
+use crate::components::{CombatStats, Experience};
+
+/// XP needed to go from `level` to the next one: 100, 200, 300, ...
+pub fn xp_to_next_level(level: u16) -> u32 {
+    100 * u32::from(level)
+}
+
+/// XP for killing a creature, from how hard it hits, how hard it is to hurt
+/// and how long it lasts.
+pub fn kill_xp(stats: &CombatStats) -> u32 {
+    u32::from(stats.max_hp) / 2 + 3 * u32::from(stats.power) + 3 * u32::from(stats.defense)
+}
+
+impl Experience {
+    /// Adds `xp`, carrying any excess over into following levels.
+    /// Returns how many levels were gained.
+    pub fn gain(&mut self, xp: u32) -> u16 {
+        self.xp += xp;
+        let start_level = self.level;
+        while self.xp >= xp_to_next_level(self.level) {
+            self.xp -= xp_to_next_level(self.level);
+            self.level += 1;
+        }
+        let gained = self.level - start_level;
+        self.pending_level_ups += gained;
+        gained
+    }
+}
+
+/// What the player can pick on the level-up screen.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum LevelUpGain {
+    MaxHp,
+    Power,
+    Defense,
+}
+
+pub const LEVEL_UP_GAINS: [LevelUpGain; 3] =
+    [LevelUpGain::MaxHp, LevelUpGain::Power, LevelUpGain::Defense];
+
+const MAX_HP_GAIN: u16 = 5;
+
+impl LevelUpGain {
+    pub fn describe(&self) -> String {
+        match self {
+            LevelUpGain::MaxHp => format!("+{} max HP", MAX_HP_GAIN),
+            LevelUpGain::Power => "+1 power".to_string(),
+            LevelUpGain::Defense => "+1 defense".to_string(),
+        }
+    }
+
+    pub fn apply(&self, stats: &mut CombatStats) {
+        match self {
+            LevelUpGain::MaxHp => {
+                stats.max_hp += MAX_HP_GAIN;
+                stats.hp += MAX_HP_GAIN;
+            }
+            LevelUpGain::Power => stats.power += 1,
+            LevelUpGain::Defense => stats.defense += 1,
+        }
+    }
+}
+
+/// Credits `xp` to `entity`, which starts at level 1 if it had no `Experience` yet.
+/// Returns the level reached, if any were gained.
+pub fn award_xp(ecs: &World, entity: Entity, xp: u32) -> Option<u16> {
+    let mut experiences = ecs.write_storage::<Experience>();
+    let experience = experiences
+        .entry(entity)
+        .ok()?
+        .or_insert_with(Experience::default);
+    (experience.gain(xp) > 0).then_some(experience.level)
+}
+
+pub fn has_pending_level_up(ecs: &World, entity: Entity) -> bool {
+    ecs.read_storage::<Experience>()
+        .get(entity)
+        .map_or(false, |experience| experience.pending_level_ups > 0)
+}
+
+/// Spends one pending level-up on `gain`.
+pub fn apply_level_up(ecs: &World, entity: Entity, gain: LevelUpGain) {
+    let mut experiences = ecs.write_storage::<Experience>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    if let (Some(experience), Some(stats)) =
+        (experiences.get_mut(entity), combat_stats.get_mut(entity))
+    {
+        experience.pending_level_ups = experience.pending_level_ups.saturating_sub(1);
+        gain.apply(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excess_xp_carries_into_later_levels() {
+        let mut experience = Experience::default();
+        assert_eq!(experience.gain(90), 0);
+        // 100 to reach level 2, then 200 of the 250 to reach level 3
+        assert_eq!(experience.gain(250), 2);
+        assert_eq!(
+            experience,
+            Experience {
+                level: 3,
+                xp: 40,
+                pending_level_ups: 2,
+            }
+        );
+    }
+}
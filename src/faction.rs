@@ -0,0 +1,77 @@
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+use specs::{prelude::*, storage::MaskedStorage};
+
+use crate::{components::Faction, raws::raws};
+
+/// The player's side.
+pub const PLAYER_FACTION: &str = "player";
+/// Monsters the raws put in no faction.
+pub const MONSTER_FACTION: &str = "monsters";
+
+/// How members of one faction treat members of another.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reaction {
+    Hostile,
+    Neutral,
+    Friendly,
+    Fearful,
+}
+
+impl Faction {
+    pub fn new(name: &str) -> Self {
+        Faction {
+            name: name.to_string(),
+        }
+    }
+
+    /// Friendly to its own, and neutral to any faction the raws give no reaction to.
+    pub fn reaction_to(&self, other: &Faction) -> Reaction {
+        if self.name == other.name {
+            Reaction::Friendly
+        } else {
+            raws()
+                .factions
+                .get(&self.name)
+                .and_then(|reactions| reactions.get(&other.name))
+                .copied()
+                .unwrap_or(Reaction::Neutral)
+        }
+    }
+}
+
+/// How `entity` treats `other`; neutral if either is in no faction.
+pub fn reaction<D>(factions: &Storage<Faction, D>, entity: Entity, other: Entity) -> Reaction
+where
+    D: Deref<Target = MaskedStorage<Faction>>,
+{
+    match (factions.get(entity), factions.get(other)) {
+        (Some(faction), Some(other_faction)) => faction.reaction_to(other_faction),
+        _ => Reaction::Neutral,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn factions_react_as_the_raws_say() {
+        let goblins = Faction::new("goblins");
+        let orcs = Faction::new("orcs");
+        let wildlife = Faction::new("wildlife");
+        assert_eq!(goblins.reaction_to(&goblins), Reaction::Friendly);
+        assert_eq!(goblins.reaction_to(&orcs), Reaction::Hostile);
+        assert_eq!(
+            wildlife.reaction_to(&Faction::new(PLAYER_FACTION)),
+            Reaction::Fearful
+        );
+        // Reactions need not be mutual, and default to indifference
+        assert_eq!(orcs.reaction_to(&wildlife), Reaction::Neutral);
+        assert_eq!(
+            Faction::new(PLAYER_FACTION).reaction_to(&Faction::new("townsfolk")),
+            Reaction::Friendly
+        );
+    }
+}
@@ -1,3 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct GameLog {
     pub entries: Vec<String>,
+    /// Show the dice rolls behind combat messages
+    pub verbose: bool,
 }
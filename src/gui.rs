@@ -3,7 +3,7 @@ use bracket_lib::{
     prelude::{BTerm, RGB},
     terminal::{
         letter_to_option, to_cp437, DistanceAlg, FontCharType, Point, VirtualKeyCode, BLACK, BLUE,
-        CYAN, MAGENTA, RED, WHITE, YELLOW,
+        CYAN, GREEN, GREY, MAGENTA, ORANGE, PINK, RED, WHITE, YELLOW,
     },
 };
 use itertools::FoldWhile::{Continue, Done};
@@ -12,14 +12,18 @@ use specs::prelude::*;
 
 use crate::{
     components::{
-        CombatStats, Equipped, HasOwner, InBackpack, Name, Player, Position, Positionable, Viewshed,
+        AiState, CombatStats, Equipped, Experience, HasOwner, InBackpack, Name, Player, Position,
+        Positionable, StatusEffects, StatusKind, Viewshed,
     },
     display_state::DisplayState,
+    experience::{xp_to_next_level, LevelUpGain, LEVEL_UP_GAINS},
     gamelog::GameLog,
     map::Map,
     player::{
         display_key_combo, get_player_pos_unwrap, get_player_unwrap, KeyBindings, PLAYER_NAME,
     },
+    run_info::{random_seed, RunInfo},
+    saveload_system::{SaveSlots, SlotSummary, SAVE_SLOTS},
     util::*,
     PsnU, RunState, State,
 };
@@ -53,7 +57,10 @@ pub fn draw_ui(ecs: &World, ctx: &mut BTerm, display_state: &DisplayState) {
             &depth,
         );
         draw_health_bar(ecs, ctx, display_state);
+        draw_experience(ecs, ctx, display_state);
+        draw_status_effects(ecs, ctx, display_state);
         draw_log(ecs, ctx, display_state);
+        draw_seed(ecs, ctx, display_state);
     }
 
     let mouse_pos = ctx.mouse_pos();
@@ -73,6 +80,101 @@ fn draw_log(ecs: &World, ctx: &mut BTerm, display_state: &DisplayState) {
         })
 }
 
+fn draw_seed(ecs: &World, ctx: &mut BTerm, display_state: &DisplayState) {
+    let seed = format!("Seed: {}", ecs.fetch::<RunInfo>().seed);
+    ctx.print_color(
+        display_state.width - 2 - seed.len() as PsnU,
+        display_state.height - 1,
+        RGB::named(GREY),
+        RGB::named(BLACK),
+        &seed,
+    );
+}
+
+fn draw_experience(ecs: &World, ctx: &mut BTerm, display_state: &DisplayState) {
+    let experiences = ecs.read_storage::<Experience>();
+    let players = ecs.read_storage::<Player>();
+    (&experiences, &players)
+        .join()
+        .for_each(|(experience, _player)| {
+            let level = format!(
+                "Level: {}  XP: {} / {}",
+                experience.level,
+                experience.xp,
+                xp_to_next_level(experience.level)
+            );
+            ctx.print_color(
+                2,
+                display_state.height - 1,
+                RGB::named(YELLOW),
+                RGB::named(BLACK),
+                &level,
+            );
+        })
+}
+
+fn status_color(kind: StatusKind) -> (u8, u8, u8) {
+    match kind {
+        StatusKind::Poison => GREEN,
+        StatusKind::Regeneration => PINK,
+        StatusKind::Haste => YELLOW,
+        StatusKind::Slow => CYAN,
+        StatusKind::Blind => GREY,
+        StatusKind::Confusion => ORANGE,
+    }
+}
+
+/// The player's status effects and turns left, e.g. "Poisoned 3", after the level.
+fn draw_status_effects(ecs: &World, ctx: &mut BTerm, display_state: &DisplayState) {
+    let statuses = ecs.read_storage::<StatusEffects>();
+    let players = ecs.read_storage::<Player>();
+    (&statuses, &players)
+        .join()
+        .for_each(|(statuses, _player)| {
+            statuses.effects.iter().fold(30, |xx, effect| {
+                let label = format!("{} {}", effect.kind.label(), effect.turns_left);
+                ctx.print_color(
+                    xx,
+                    display_state.height - 1,
+                    RGB::named(status_color(effect.kind)),
+                    RGB::named(BLACK),
+                    &label,
+                );
+                xx + label.len() as PsnU + 2
+            });
+        })
+}
+
+/// Debug view of the monsters' AI: each one's state beside it, and an `x` where it is headed.
+pub fn draw_ai_overlay(ecs: &World, ctx: &mut BTerm) {
+    let positions = ecs.read_storage::<Position>();
+    let ai_states = ecs.read_storage::<AiState>();
+    (&positions, &ai_states).join().for_each(|(pos, ai_state)| {
+        let (color, target_opt) = match ai_state {
+            AiState::Idle => (GREY, None),
+            AiState::Wandering(destination) => (CYAN, Some(destination)),
+            AiState::Chasing(last_seen, _) => (RED, Some(last_seen)),
+            AiState::Fleeing => (YELLOW, None),
+        };
+        if let Some(target) = target_opt {
+            ctx.set(
+                target.xx,
+                target.yy,
+                RGB::named(color),
+                RGB::named(BLACK),
+                to_cp437('x'),
+            );
+        }
+        ctx.print_color(
+            pos.xx + 1,
+            pos.yy,
+            RGB::named(color),
+            RGB::named(BLACK),
+            &ai_state.to_string(),
+        );
+    })
+}
+
 fn draw_health_bar(ecs: &World, ctx: &mut BTerm, display_state: &DisplayState) {
     let combat_stats = ecs.read_storage::<CombatStats>();
     let players = ecs.read_storage::<Player>();
@@ -106,13 +208,17 @@ fn draw_tooltips(ecs: &World, ctx: &mut BTerm) {
     let map = ecs.fetch::<Map>();
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
+    let ai_states = ecs.read_storage::<AiState>();
 
     let mouse_pos = ctx.mouse_pos();
     if mouse_pos.0 < (map.width() as i32) && mouse_pos.1 < (map.height() as i32) {
-        let tooltip = (&names, &positions)
+        let tooltip = (&names, &positions, ai_states.maybe())
             .join()
-            .filter(|(_name, pos)| (**pos) == mouse_pos.from())
-            .map(|(name, _pos)| name.name.to_string())
+            .filter(|(_name, pos, _ai)| (**pos) == mouse_pos.from())
+            .map(|(name, _pos, ai_state)| match ai_state {
+                Some(AiState::Fleeing) => format!("{} (fleeing)", name.name),
+                _ => name.name.to_string(),
+            })
             .collect::<Vec<String>>();
 
         if !tooltip.is_empty() {
@@ -175,13 +281,6 @@ pub fn equipped_items(ecs: &World, owner: Entity) -> Vec<(Entity, String)> {
         .map(|(ent, _b, name)| (ent, name.name.to_string()))
         .collect::<Vec<(Entity, String)>>()
 }
-//
-pub fn owned_items(ecs: &World, owner: Entity) -> Vec<(Entity, String)> {
-    // Seems difficult to avoid mutation here given the APIs on hand
-    let mut equipped_items = equipped_items(ecs, owner);
-    equipped_items.append(&mut backpack_items(ecs, owner));
-    equipped_items
-}
 
 #[derive(Clone, Debug)]
 pub enum InventoryMode {
@@ -333,42 +432,40 @@ pub fn show_inventory(
     };
     let useable: Vec<Entity> = abs_pack
         .into_iter()
+        // Monsters carry things too
+        .filter(|(_entity, item, _name)| item.owner() == player_entity)
         .enumerate()
-        .filter_map(|(jj, (entity, item, name))| {
-            if item.owner() == player_entity {
-                ctx.set(
-                    x_init + 1,
-                    y_init + jj as PsnU,
-                    RGB::named(WHITE),
-                    RGB::named(BLACK),
-                    to_cp437('('),
-                );
-                ctx.set(
-                    // assign the item a letter in the menu
-                    x_init + 2,
-                    y_init + jj as PsnU,
-                    RGB::named(WHITE),
-                    RGB::named(BLACK),
-                    97 + jj as FontCharType,
-                );
-                ctx.set(
-                    x_init + 3,
-                    y_init + jj as PsnU,
-                    RGB::named(WHITE),
-                    RGB::named(BLACK),
-                    to_cp437(')'),
-                );
-                ctx.print_color(
-                    x_init + 4,
-                    y_init + jj as PsnU,
-                    RGB::named(WHITE),
-                    RGB::named(BLACK),
-                    &name.name,
-                );
-                Some(entity)
-            } else {
-                None
-            }
+        .map(|(jj, (entity, _item, name))| {
+            ctx.set(
+                x_init + 1,
+                y_init + jj as PsnU,
+                RGB::named(WHITE),
+                RGB::named(BLACK),
+                to_cp437('('),
+            );
+            ctx.set(
+                // assign the item a letter in the menu
+                x_init + 2,
+                y_init + jj as PsnU,
+                RGB::named(WHITE),
+                RGB::named(BLACK),
+                97 + jj as FontCharType,
+            );
+            ctx.set(
+                x_init + 3,
+                y_init + jj as PsnU,
+                RGB::named(WHITE),
+                RGB::named(BLACK),
+                to_cp437(')'),
+            );
+            ctx.print_color(
+                x_init + 4,
+                y_init + jj as PsnU,
+                RGB::named(WHITE),
+                RGB::named(BLACK),
+                &name.name,
+            );
+            entity
         })
         .collect();
 
@@ -390,6 +487,41 @@ pub fn show_inventory(
     }
 }
 
+/// Asks which gain to take for a new level; there is no way to skip it.
+pub fn level_up_menu(gs: &State, ctx: &mut BTerm) -> Option<LevelUpGain> {
+    let title = "Level Up! Choose a gain:";
+    let x_init = 15;
+    let y_init = (gs.display.height - LEVEL_UP_GAINS.len() as PsnU) / 2;
+    ctx.draw_box(
+        x_init,
+        y_init - 2,
+        title.len() + 4,
+        LEVEL_UP_GAINS.len() + 3,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+    );
+    ctx.print_color(
+        x_init + 3,
+        y_init - 2,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        title,
+    );
+    LEVEL_UP_GAINS.iter().enumerate().for_each(|(jj, gain)| {
+        ctx.print(
+            x_init + 1,
+            y_init + jj as PsnU,
+            format!("({}) {}", (b'a' + jj as u8) as char, gain.describe()),
+        );
+    });
+
+    ctx.key.and_then(|key| {
+        letter_to_option(key)
+            .clamp_opt(0, LEVEL_UP_GAINS.len() as i32 - 1)
+            .map(|selection| LEVEL_UP_GAINS[selection as usize])
+    })
+}
+
 pub fn ranged_target(
     gs: &State,
     ctx: &mut BTerm,
@@ -488,6 +620,12 @@ pub enum MainMenuStatus {
     Selected,
 }
 
+/// A one-off message shown on the main menu, e.g. why a save could not be loaded.
+#[derive(Default)]
+pub struct MenuNotice {
+    pub text: Option<String>,
+}
+
 pub struct MainMenuResult {
     pub highlighted: MainMenuSelection,
     pub status: MainMenuStatus,
@@ -513,6 +651,15 @@ pub fn main_menu(gs: &State, ctx: &mut BTerm) -> MainMenuResult {
     let runstate = gs.ecs.fetch::<RunState>();
 
     ctx.print_color_centered(15, RGB::named(YELLOW), RGB::named(BLACK), "Rusty Rogue");
+    ctx.print_color_centered(
+        17,
+        RGB::named(GREY),
+        RGB::named(BLACK),
+        format!("Seed: {}", gs.ecs.fetch::<RunInfo>().seed),
+    );
+    if let Some(text) = &gs.ecs.fetch::<MenuNotice>().text {
+        ctx.print_color_centered(20, RGB::named(RED), RGB::named(BLACK), text);
+    }
 
     if let RunState::MainMenu {
         menu_selection: selection,
@@ -558,6 +705,251 @@ pub fn main_menu(gs: &State, ctx: &mut BTerm) -> MainMenuResult {
     }
 }
 
+/// Choices made on the new game menu before a run starts.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct NewGameOptions {
+    pub seed: u64,
+    pub autosave: bool,
+    pub permadeath: bool,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum NewGameMenuResult {
+    NoSelection { options: NewGameOptions },
+    Start { options: NewGameOptions },
+    Cancel,
+}
+
+fn on_off(flag: bool) -> &'static str {
+    if flag {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn key_digit(key: VirtualKeyCode) -> Option<u64> {
+    match key {
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Some(0),
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Some(1),
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Some(2),
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Some(3),
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Some(4),
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Some(5),
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Some(6),
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Some(7),
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Some(8),
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Some(9),
+        _ => None,
+    }
+}
+
+pub fn new_game_menu(ctx: &mut BTerm, options: NewGameOptions) -> NewGameMenuResult {
+    let seed = options.seed;
+    ctx.print_color_centered(15, RGB::named(YELLOW), RGB::named(BLACK), "New Game");
+    ctx.print_color_centered(
+        18,
+        RGB::named(MAGENTA),
+        RGB::named(BLACK),
+        format!("Seed: {}", seed),
+    );
+    ctx.print_color_centered(
+        19,
+        RGB::named(MAGENTA),
+        RGB::named(BLACK),
+        format!(
+            "Autosave: {}   Permadeath: {}",
+            on_off(options.autosave),
+            on_off(options.permadeath)
+        ),
+    );
+    ctx.print_color_centered(
+        21,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+        "Type a seed; BACKSPACE erases a digit, R picks a random seed.",
+    );
+    ctx.print_color_centered(
+        22,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+        "A toggles autosave on each new level; P toggles permadeath.",
+    );
+    ctx.print_color_centered(
+        24,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+        "ENTER to start, ESCAPE to go back.",
+    );
+
+    let with_seed = |seed| NewGameMenuResult::NoSelection {
+        options: NewGameOptions { seed, ..options },
+    };
+    match ctx.key {
+        None => NewGameMenuResult::NoSelection { options },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => NewGameMenuResult::Cancel,
+            VirtualKeyCode::Return => NewGameMenuResult::Start { options },
+            VirtualKeyCode::Back => with_seed(seed / 10),
+            VirtualKeyCode::R => with_seed(random_seed()),
+            VirtualKeyCode::A => NewGameMenuResult::NoSelection {
+                options: NewGameOptions {
+                    autosave: !options.autosave,
+                    ..options
+                },
+            },
+            VirtualKeyCode::P => NewGameMenuResult::NoSelection {
+                options: NewGameOptions {
+                    permadeath: !options.permadeath,
+                    ..options
+                },
+            },
+            _ => with_seed(
+                key_digit(key)
+                    .and_then(|digit| seed.checked_mul(10)?.checked_add(digit))
+                    .unwrap_or(seed),
+            ),
+        },
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SaveSlotMode {
+    Save,
+    Load,
+}
+
+/// An action on an occupied slot that waits for a yes/no answer.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SlotConfirm {
+    Overwrite,
+    Delete,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum SaveSlotMenuResult {
+    NoSelection {
+        selection: usize,
+        confirm: Option<SlotConfirm>,
+    },
+    Save(usize),
+    Load(usize),
+    Delete(usize),
+    Cancel,
+}
+
+fn slot_description(summary: &SlotSummary) -> String {
+    match summary {
+        SlotSummary::Empty => "(empty)".to_string(),
+        SlotSummary::Saved(header) => format!(
+            "Depth {}, HP {}/{}, turn {}, {}, seed {}",
+            header.depth,
+            header.hp,
+            header.max_hp,
+            header.turns,
+            fmt_utc_timestamp(header.timestamp),
+            header.seed
+        ),
+        SlotSummary::OldFormat => "(older save, no preview)".to_string(),
+        SlotSummary::Unreadable => "(unreadable)".to_string(),
+    }
+}
+
+pub fn save_slot_menu(
+    gs: &State,
+    ctx: &mut BTerm,
+    mode: SaveSlotMode,
+    selection: usize,
+    confirm: Option<SlotConfirm>,
+) -> SaveSlotMenuResult {
+    let slots = gs.ecs.fetch::<SaveSlots>();
+    let title = match mode {
+        SaveSlotMode::Save => "Save Game",
+        SaveSlotMode::Load => "Load Game",
+    };
+    ctx.print_color_centered(15, RGB::named(YELLOW), RGB::named(BLACK), title);
+    slots
+        .summaries
+        .iter()
+        .enumerate()
+        .for_each(|(slot, summary)| {
+            ctx.print_color_centered(
+                18 + 2 * slot as i32,
+                if slot == selection {
+                    RGB::named(MAGENTA)
+                } else {
+                    RGB::named(WHITE)
+                },
+                RGB::named(BLACK),
+                format!("{}. {}", slot + 1, slot_description(summary)),
+            );
+            if matches!(summary, SlotSummary::Saved(header) if header.permadeath) {
+                ctx.print_color_centered(
+                    19 + 2 * slot as i32,
+                    RGB::named(RED),
+                    RGB::named(BLACK),
+                    "permadeath: loading uses this save up",
+                );
+            }
+        });
+    let help_row = 19 + 2 * slots.summaries.len() as i32;
+    let permadeath_slot = match mode {
+        SaveSlotMode::Save => gs.ecs.fetch::<RunInfo>().permadeath_slot(),
+        SaveSlotMode::Load => None,
+    };
+    if let Some(slot) = permadeath_slot {
+        ctx.print_color_centered(
+            help_row + 1,
+            RGB::named(RED),
+            RGB::named(BLACK),
+            format!(
+                "This permadeath run can only be saved to slot {}.",
+                slot + 1
+            ),
+        );
+    }
+    let help = match confirm {
+        Some(SlotConfirm::Overwrite) => format!("Overwrite slot {}? (Y/N)", selection + 1),
+        Some(SlotConfirm::Delete) => format!("Delete slot {}? (Y/N)", selection + 1),
+        None => "ENTER to choose a slot, DELETE to erase it, ESCAPE to go back.".to_string(),
+    };
+    ctx.print_color_centered(help_row, RGB::named(WHITE), RGB::named(BLACK), help);
+
+    let occupied = slots
+        .summaries
+        .get(selection)
+        .is_some_and(|summary| !summary.is_empty());
+    let no_selection = |selection, confirm| SaveSlotMenuResult::NoSelection { selection, confirm };
+    match (ctx.key, confirm) {
+        (None, _) => no_selection(selection, confirm),
+        (Some(VirtualKeyCode::Y), Some(SlotConfirm::Overwrite)) => {
+            SaveSlotMenuResult::Save(selection)
+        }
+        (Some(VirtualKeyCode::Y), Some(SlotConfirm::Delete)) => {
+            SaveSlotMenuResult::Delete(selection)
+        }
+        (Some(_), Some(_)) => no_selection(selection, None),
+        (Some(key), None) => match key {
+            VirtualKeyCode::Escape => SaveSlotMenuResult::Cancel,
+            VirtualKeyCode::Up => no_selection((selection + SAVE_SLOTS - 1) % SAVE_SLOTS, None),
+            VirtualKeyCode::Down => no_selection((selection + 1) % SAVE_SLOTS, None),
+            VirtualKeyCode::Delete | VirtualKeyCode::Back if occupied => {
+                no_selection(selection, Some(SlotConfirm::Delete))
+            }
+            VirtualKeyCode::Return if permadeath_slot.is_some_and(|slot| slot != selection) => {
+                no_selection(selection, None)
+            }
+            VirtualKeyCode::Return => match (mode, occupied) {
+                (SaveSlotMode::Save, false) => SaveSlotMenuResult::Save(selection),
+                (SaveSlotMode::Save, true) => no_selection(selection, Some(SlotConfirm::Overwrite)),
+                (SaveSlotMode::Load, true) => SaveSlotMenuResult::Load(selection),
+                (SaveSlotMode::Load, false) => no_selection(selection, None),
+            },
+            _ => no_selection(selection, None),
+        },
+    }
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum GameOverResult {
     NoSelection,
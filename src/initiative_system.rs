@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use specs::prelude::*;
+
+use crate::{
+    components::{Equipped, Initiative, Item, MyTurn, Player, Position, StatusEffects, StatusKind},
+    RunState,
+};
+
+/// Energy spent on each turn, and so gained per tick at normal speed.
+pub const ACTION_COST: i32 = 100;
+pub const NORMAL_SPEED: i32 = ACTION_COST;
+
+impl Initiative {
+    pub fn new(speed: i32) -> Self {
+        Initiative { speed, energy: 0 }
+    }
+}
+
+/// Speed after status effects and the weight of equipment; always at least 1,
+/// so that everyone gets a turn eventually.
+pub fn effective_speed(base: i32, statuses: Option<&StatusEffects>, speed_penalty: i32) -> i32 {
+    let has = |kind| statuses.map_or(false, |effects| effects.has(kind));
+    let speed = if has(StatusKind::Haste) {
+        base * 2
+    } else if has(StatusKind::Slow) {
+        base / 2
+    } else {
+        base
+    };
+    (speed - speed_penalty).max(1)
+}
+
+/// Ticks until `initiative` has the energy for a turn at `speed`.
+fn ticks_until_turn(initiative: &Initiative, speed: i32) -> i32 {
+    let needed = (ACTION_COST - initiative.energy).max(0);
+    (needed + speed - 1) / speed
+}
+
+/// The scheduler: while ticking, hands out energy until someone can act and marks
+/// everyone who can with `MyTurn`. When the player is among them, it waits for input.
+pub struct InitiativeSystem {}
+
+impl<'a> System<'a> for InitiativeSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, RunState>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Item>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Initiative>,
+        WriteStorage<'a, MyTurn>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            mut runstate,
+            players,
+            statuses,
+            items,
+            equipped,
+            positions,
+            mut initiatives,
+            mut my_turns,
+        ) = data;
+
+        my_turns.clear();
+        if *runstate != RunState::Ticking {
+            return;
+        }
+        let mut speed_penalties: HashMap<Entity, i32> = HashMap::new();
+        (&items, &equipped).join().for_each(|(item, eq)| {
+            if let Some(et) = item.equip_opt() {
+                *speed_penalties.entry(eq.owner).or_default() += et.speed_penalty();
+            }
+        });
+        // Each entity's speed and how many ticks it needs for a turn. Those frozen on other
+        // levels have no position, and neither gain energy nor hold up everyone else.
+        let schedule: Vec<(Entity, i32, i32)> = (&entities, &initiatives, &positions)
+            .join()
+            .map(|(entity, initiative, _pos)| {
+                let speed_penalty = speed_penalties.get(&entity).copied().unwrap_or_default();
+                let speed = effective_speed(initiative.speed, statuses.get(entity), speed_penalty);
+                (entity, speed, ticks_until_turn(initiative, speed))
+            })
+            .collect();
+        let ticks = match schedule.iter().map(|(_, _, ticks)| *ticks).min() {
+            Some(ticks) => ticks,
+            // Nobody to schedule
+            None => {
+                *runstate = RunState::AwaitingInput;
+                return;
+            }
+        };
+
+        schedule.into_iter().for_each(|(entity, speed, _)| {
+            if let Some(initiative) = initiatives.get_mut(entity) {
+                initiative.energy += ticks * speed;
+                if initiative.energy >= ACTION_COST {
+                    initiative.energy -= ACTION_COST;
+                    my_turns
+                        .insert(entity, MyTurn {})
+                        .expect("Unable to insert turn");
+                    if players.get(entity).is_some() {
+                        *runstate = RunState::AwaitingInput;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{OtherLevelPosition, StatusEffect};
+
+    fn scheduling_world() -> World {
+        let mut world = World::new();
+        world.register::<Player>();
+        world.register::<StatusEffects>();
+        world.register::<Item>();
+        world.register::<Equipped>();
+        world.register::<Position>();
+        world.register::<OtherLevelPosition>();
+        world.register::<Initiative>();
+        world.register::<MyTurn>();
+        world.insert(RunState::Ticking);
+        world
+    }
+
+    /// Runs the scheduler for a tick, returning whoever it gave a turn.
+    fn tick(world: &World) -> Vec<Entity> {
+        *world.write_resource::<RunState>() = RunState::Ticking;
+        InitiativeSystem {}.run_now(world);
+        (&world.entities(), &world.read_storage::<MyTurn>())
+            .join()
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    #[test]
+    fn fast_entities_act_more_often() {
+        let mut world = scheduling_world();
+        let pos = Position { xx: 1, yy: 1 };
+        let player = world
+            .create_entity()
+            .with(Player {})
+            .with(pos)
+            .with(Initiative::new(NORMAL_SPEED))
+            .build();
+        let fast = world
+            .create_entity()
+            .with(pos)
+            .with(Initiative::new(2 * NORMAL_SPEED))
+            .build();
+        let slow = world
+            .create_entity()
+            .with(pos)
+            .with(Initiative::new(NORMAL_SPEED / 2))
+            .build();
+
+        let mut turns = HashMap::new();
+        loop {
+            let acting = tick(&world);
+            // Stop short of the player's fifth turn, four ticks in
+            if acting.contains(&player) && turns.get(&player) == Some(&4) {
+                break;
+            }
+            acting
+                .into_iter()
+                .for_each(|entity| *turns.entry(entity).or_insert(0) += 1);
+        }
+        assert_eq!(turns.get(&fast), Some(&8));
+        assert_eq!(turns.get(&slow), Some(&2));
+    }
+
+    #[test]
+    fn frozen_levels_wait_for_the_player() {
+        let mut world = scheduling_world();
+        let pos = Position { xx: 1, yy: 1 };
+        let player = world
+            .create_entity()
+            .with(Player {})
+            .with(pos)
+            .with(Initiative::new(NORMAL_SPEED))
+            .build();
+        // Left behind on another level, however fast
+        let frozen = world
+            .create_entity()
+            .with(OtherLevelPosition { depth: 2, pos })
+            .with(Initiative::new(4 * NORMAL_SPEED))
+            .build();
+
+        (0..3).for_each(|_| assert_eq!(tick(&world), vec![player]));
+        assert_eq!(
+            world
+                .read_storage::<Initiative>()
+                .get(frozen)
+                .map(|initiative| initiative.energy),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn effects_and_equipment_change_speed() {
+        let mut statuses = StatusEffects::default();
+        assert_eq!(effective_speed(100, Some(&statuses), 10), 90);
+        statuses.add(StatusEffect {
+            kind: StatusKind::Haste,
+            turns_left: 3,
+            magnitude: 1,
+        });
+        assert_eq!(effective_speed(100, Some(&statuses), 0), 200);
+        statuses.add(StatusEffect {
+            kind: StatusKind::Slow,
+            turns_left: 3,
+            magnitude: 1,
+        });
+        assert_eq!(effective_speed(100, Some(&statuses), 0), 50);
+        assert_eq!(effective_speed(5, None, 10), 1);
+    }
+}
@@ -4,13 +4,15 @@ use std::fmt::{Display, Formatter};
 use crate::util_ecs::EcsActionMsgData;
 use crate::{
     components::{
-        AreaOfEffect, CombatStats, Confusion, Consumable, Equipped, EventIncomingDamage,
-        EventWantsToDropItem, EventWantsToRemoveItem, EventWantsToUseItem, InflictsDamage, IsItem,
-        Item, ProvidesHealing,
+        Ammunition, AreaOfEffect, CombatStats, Consumable, Equipped, EventIncomingDamage,
+        EventWantsToDropItem, EventWantsToRemoveItem, EventWantsToUseItem, InflictsDamage,
+        InflictsStatus, IsItem, Item, LastHitBy, ProvidesHealing, Spell, StatusEffects, StatusKind,
+        Viewshed,
     },
     equipment::{get_equipped_items, EquipSlot},
     map::Map,
     player::PLAYER_NAME,
+    status_effect_system::inflict,
     util::fmt_list,
 };
 use bracket_lib::prelude::field_of_view;
@@ -27,40 +29,76 @@ use crate::entity_action_msg_no_ecs;
 impl<'a> System<'a> for ItemCollectionSystem {
     type SystemData = (
         Entities<'a>,
+        ReadExpect<'a, Map>,
         ReadStorage<'a, Player>,
         WriteExpect<'a, GameLog>,
-        ReadStorage<'a, Name>,
+        WriteStorage<'a, Name>,
         WriteStorage<'a, InBackpack>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, EventWantsToPickupItem>,
+        WriteStorage<'a, Ammunition>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (entities, players, mut log, names, mut backpack, mut positions, mut wants_pickup) =
-            data;
+        let (
+            entities,
+            map,
+            players,
+            mut log,
+            mut names,
+            mut backpack,
+            mut positions,
+            mut wants_pickup,
+            mut ammunition,
+        ) = data;
 
         // TODO: (multi-player: fix this to be player-specific -
         // prevent other players from picking up the same item)
-        (&entities, &players, &mut wants_pickup).join().for_each(
-            |(player_entity, _player, pickup)| {
+        (&entities, &mut wants_pickup)
+            .join()
+            .for_each(|(collector, pickup)| {
+                let item_name = names.get(pickup.item).unwrap().name.clone();
+                if players.get(collector).is_some() {
+                    log.entries.push(format!("You pick up the {}.", item_name));
+                } else if positions
+                    .get(pickup.item)
+                    .map_or(false, |pos| map.visible_tiles[map.pos_idx(*pos)])
+                {
+                    if let Some(collector_name) = names.get(collector) {
+                        log.entries.push(format!(
+                            "{} picks up the {}.",
+                            collector_name.name, item_name
+                        ));
+                    }
+                }
+
+                // Ammo joins a stack of the same kind already in the backpack
+                let stack_opt = ammunition.get(pickup.item).and_then(|picked| {
+                    (&entities, &ammunition, &backpack)
+                        .join()
+                        .find(|(_, ammo, bpack)| {
+                            bpack.owner == collector && ammo.kind == picked.kind
+                        })
+                        .map(|(stack, ammo, _)| (stack, ammo.count + picked.count))
+                });
+                if let Some((stack, count)) = stack_opt {
+                    if let Some(ammo) = ammunition.get_mut(stack) {
+                        ammo.count = count;
+                        if let Some(name) = names.get_mut(stack) {
+                            name.name = ammo.name();
+                        }
+                    }
+                    entities
+                        .delete(pickup.item)
+                        .unwrap_or_else(|er| panic!("Unable to merge ammo stacks!: {}", er));
+                    return;
+                }
+
                 positions.remove(pickup.item);
                 backpack
-                    .insert(
-                        pickup.item,
-                        InBackpack {
-                            owner: player_entity,
-                        },
-                    )
+                    .insert(pickup.item, InBackpack { owner: collector })
                     .unwrap_or_else(|er| panic!("Unable to insert item into backpack!: {}", er));
-
-                if pickup.collected_by == player_entity {
-                    log.entries.push(format!(
-                        "You pick up the {}.",
-                        names.get(pickup.item).unwrap().name
-                    ));
-                }
-            },
-        );
+            });
         wants_pickup.clear();
     }
 }
@@ -74,7 +112,8 @@ type ItemUseSystemData<'a> = (
     ReadStorage<'a, Name>,
     ReadStorage<'a, ProvidesHealing>,
     ReadStorage<'a, InflictsDamage>,
-    WriteStorage<'a, Confusion>,
+    ReadStorage<'a, InflictsStatus>,
+    WriteStorage<'a, StatusEffects>,
     ReadStorage<'a, AreaOfEffect>,
     WriteStorage<'a, CombatStats>,
     WriteStorage<'a, EventIncomingDamage>,
@@ -82,6 +121,10 @@ type ItemUseSystemData<'a> = (
     ReadStorage<'a, Item>,
     WriteStorage<'a, Equipped>,
     WriteStorage<'a, InBackpack>,
+    WriteStorage<'a, LastHitBy>,
+    WriteStorage<'a, Viewshed>,
+    ReadStorage<'a, Spell>,
+    ReadStorage<'a, Position>,
 );
 
 type EquipData<'a, 'b, I> = (
@@ -226,7 +269,8 @@ impl<'a> System<'a> for ItemUseSystem {
             names,
             healing,
             damaging,
-            mut confused,
+            inflicts_status,
+            mut statuses,
             aoe,
             mut combat_stats,
             mut incoming_damage,
@@ -234,27 +278,38 @@ impl<'a> System<'a> for ItemUseSystem {
             items,
             mut equipped,
             mut backpack,
+            mut last_hit_by,
+            mut viewsheds,
+            spells,
+            positions,
         ) = data;
 
-        let delete_if_consumed = |item: Entity, used: bool, player_name: &Name| {
+        let delete_if_consumed = |item: Entity, used: bool, user_name: &Name| {
             let consumable = consumables.get(item);
             match consumable {
                 None => {}
                 Some(_) => {
                     if used {
                         entities.delete(item).unwrap_or_else(|er| {
-                            panic!("Delete item failed for player {}: {}", player_name.name, er)
+                            panic!("Delete item failed for {}: {}", user_name.name, er)
                         });
                     }
                 }
             }
         };
 
-        (&entities, &players, &mut wants_use_item, &names)
+        // Monsters use items too, and cast their spells the same way
+        (&entities, &mut wants_use_item, &names)
             .join()
-            .for_each(|(player_entity, _player, useitem, player_name)| {
+            .for_each(|(user, useitem, user_name)| {
+                let item_name = &names.get(useitem.item).unwrap().name;
+                let uses_item = if spells.get(useitem.item).is_some() {
+                    format!("casts {}", item_name)
+                } else {
+                    format!("uses the {}", item_name)
+                };
                 let targets = match useitem.target {
-                    None => vec![player_entity],
+                    None => vec![user],
                     Some(target) => {
                         let area_effect = aoe.get(useitem.item);
                         match area_effect {
@@ -263,8 +318,7 @@ impl<'a> System<'a> for ItemUseSystem {
                                 map.tile_content[map.pos_idx(target)].to_vec()
                             }
                             Some(ae) => {
-                                let fov_tiles =
-                                    field_of_view(target, ae.radius.into(), &*map);
+                                let fov_tiles = field_of_view(target, ae.radius.into(), &*map);
                                 let blast_tiles = fov_tiles.into_iter().filter(|pos| {
                                     pos.x >= 0
                                         && pos.x < map.width_psnu as i32
@@ -279,20 +333,37 @@ impl<'a> System<'a> for ItemUseSystem {
                         }
                     }
                 };
+                // What monsters do is only news if the player sees it or is on the receiving end
+                let witnessed = positions
+                    .get(user)
+                    .map_or(false, |pos| map.visible_tiles[map.pos_idx(*pos)])
+                    || targets.iter().any(|target| players.get(*target).is_some());
                 if let Some(Item::Equippable(equip)) = items.get(useitem.item) {
                     targets.first().iter().for_each(|target| {
-                        let player_equip = get_equipped_items(&entities, &items, &equipped, player_entity);
-                        let equipped_items: HashSet<(Entity, Item)> = player_equip.iter().map(|kv| (kv.1.1, Item::Equippable(kv.1.0.clone()))).collect();
-                        let new_equip = Equipped::new(**target, &player_equip, &equip.allowed_slots);
+                        let player_equip = get_equipped_items(&entities, &items, &equipped, user);
+                        let equipped_items: HashSet<(Entity, Item)> = player_equip
+                            .iter()
+                            .map(|kv| (kv.1 .1, Item::Equippable(kv.1 .0.clone())))
+                            .collect();
+                        let new_equip =
+                            Equipped::new(**target, &player_equip, &equip.allowed_slots);
                         let equip_changes = equip_slot(
-                            (&entities, &mut backpack, &items, &mut equipped, &names)
-                            , new_equip, useitem.item,  EquipChanges::new( equipped_items)
+                            (&entities, &mut backpack, &items, &mut equipped, &names),
+                            new_equip,
+                            useitem.item,
+                            EquipChanges::new(equipped_items),
                         );
                         let ecs_data = EcsActionMsgData::new(&entities, &players, &names);
-                        if let Some(equip_msg) = equip_message(ecs_data, equip_changes, player_entity) {
+                        if let Some(equip_msg) = equip_message(ecs_data, equip_changes, user)
+                            .filter(|_| user_name.name == PLAYER_NAME || witnessed)
+                        {
                             log.entries.push(equip_msg);
                         }
-                        });
+                        // Equipment may change how far the wearer sees
+                        if let Some(viewshed) = viewsheds.get_mut(user) {
+                            viewshed.dirty = true;
+                        }
+                    });
                 };
                 let item_heals = healing.get(useitem.item);
                 match item_heals {
@@ -303,12 +374,16 @@ impl<'a> System<'a> for ItemUseSystem {
                                 panic!("Unable to get combat stats for target {}!", target.id())
                             });
                             stats.hp = u16::min(stats.max_hp, stats.hp + healer.heal_amount);
-                            delete_if_consumed(useitem.item, /* used = */ true, player_name);
-                            if player_name.name == PLAYER_NAME {
+                            delete_if_consumed(useitem.item, /* used = */ true, user_name);
+                            if user_name.name == PLAYER_NAME {
                                 log.entries.push(format!(
                                     "You consume the {}, healing {} hp.",
-                                    names.get(useitem.item).unwrap().name,
-                                    healer.heal_amount
+                                    item_name, healer.heal_amount
+                                ));
+                            } else if witnessed {
+                                log.entries.push(format!(
+                                    "{} {}, healing {} hp.",
+                                    user_name.name, uses_item, healer.heal_amount
                                 ));
                             }
                         });
@@ -318,6 +393,12 @@ impl<'a> System<'a> for ItemUseSystem {
                 match item_damages {
                     None => {}
                     Some(damage) => {
+                        let victim_names: Vec<String> = targets
+                            .iter()
+                            .filter(|victim| combat_stats.get(**victim).is_some())
+                            .filter_map(|victim| names.get(*victim))
+                            .map(|name| name.name.clone())
+                            .collect();
                         let used = targets
                             .iter()
                             .map(|victim| {
@@ -325,48 +406,78 @@ impl<'a> System<'a> for ItemUseSystem {
                                     &mut incoming_damage,
                                     *victim,
                                     damage.damage,
-                                )
+                                );
+                                LastHitBy::record(&mut last_hit_by, *victim, user)
                             })
                             .count()
                             > 0;
-                        delete_if_consumed(useitem.item, used, player_name);
-                        if used && player_name.name == PLAYER_NAME {
+                        delete_if_consumed(useitem.item, used, user_name);
+                        if used && user_name.name == PLAYER_NAME {
                             log.entries.push(format!(
                                 "You use the {}, inflicting {} damage.",
-                                names.get(useitem.item).unwrap().name,
-                                damage.damage
+                                item_name, damage.damage
+                            ));
+                        } else if used && witnessed {
+                            log.entries.push(format!(
+                                "{} {}, inflicting {} damage on {}.",
+                                user_name.name,
+                                uses_item,
+                                damage.damage,
+                                fmt_list(&victim_names)
                             ));
                         }
                     }
                 }
-                let confuser_item = confused.get(useitem.item).cloned();
-                match confuser_item {
-                    None => {}
-                    Some(confusion) => {
-                        let used = targets
-                            .iter()
-                            .map(|victim| {
-                                confused
-                                    .insert(*victim, confusion.clone())
-                                    .unwrap_or_else(|er| {
-                                        panic!(
-                                            "Unable to insert confusion component for target {}!: {}",
-                                            victim.id(),
-                                            er
-                                        )
-                                    });
-                                if player_name.name == PLAYER_NAME {
-                                    log.entries.push(format!(
-                                        "You use {} on {}, confusing them.",
-                                        names.get(useitem.item).unwrap().name,
-                                        names.get(*victim).unwrap().name
-                                    ));
+                if let Some(inflicts) = inflicts_status.get(useitem.item) {
+                    let effect = inflicts.effect;
+                    let used = targets
+                        .iter()
+                        .filter(|victim| combat_stats.get(**victim).is_some())
+                        .map(|victim| {
+                            inflict(&mut statuses, *victim, effect);
+                            if effect.kind == StatusKind::Blind {
+                                if let Some(viewshed) = viewsheds.get_mut(*victim) {
+                                    viewshed.dirty = true;
                                 }
-                            })
-                            .count()
-                            > 0;
-                        delete_if_consumed(useitem.item, used, player_name);
-                    }
+                            }
+                            let victim_name = &names.get(*victim).unwrap().name;
+                            if user_name.name == PLAYER_NAME {
+                                log.entries.push(if *victim == user {
+                                    format!(
+                                        "You use the {}, and are {}.",
+                                        item_name,
+                                        effect.kind.adjective()
+                                    )
+                                } else {
+                                    format!(
+                                        "You use the {} on {}, leaving them {}.",
+                                        item_name,
+                                        victim_name,
+                                        effect.kind.adjective()
+                                    )
+                                });
+                            } else if witnessed {
+                                log.entries.push(if *victim == user {
+                                    format!(
+                                        "{} {}, and is {}.",
+                                        user_name.name,
+                                        uses_item,
+                                        effect.kind.adjective()
+                                    )
+                                } else {
+                                    format!(
+                                        "{} {} on {}, leaving them {}.",
+                                        user_name.name,
+                                        uses_item,
+                                        victim_name,
+                                        effect.kind.adjective()
+                                    )
+                                });
+                            }
+                        })
+                        .count()
+                        > 0;
+                    delete_if_consumed(useitem.item, used, user_name);
                 }
             });
         wants_use_item.clear();
@@ -401,6 +512,7 @@ where
             slot2.clone(),
         )
     }))
+    .unique_by(|(item_ent, _, _)| *item_ent)
     .collect_vec();
 
     let unequipped: HashSet<(Entity, Item)> = HashSet::from_iter(
@@ -492,9 +604,12 @@ where
     I: Copy,
     I::Type: IsItem,
 {
+    // A two-handed item sits in both hands, so check its extra slot too
     (entities, equipped, items)
         .join()
-        .filter(|(_, eq, _)| eq.owner == owner && eq.slot == slot)
+        .filter(|(_, eq, _)| {
+            eq.owner == owner && (eq.slot == slot || eq.slot_extra.as_ref() == Some(&slot))
+        })
         .map(|(ent, eq, item)| (ent, eq.clone(), item.from()))
         .collect()
 }
@@ -505,6 +620,10 @@ fn equip_message(
     owner: Entity,
 ) -> Option<String> {
     let bonus_changes = equip_changes.clone().bonus_changes();
+    let wields_2h = equip_changes
+        .equipped
+        .iter()
+        .any(|(_, item)| item.equip_opt().map_or(false, |eq| eq.is_2h()));
     let equip_names = equip_changes
         .equipped
         .into_iter()
@@ -531,6 +650,13 @@ fn equip_message(
             owner,
             "equip"
         )),
+        (false, false) if wields_2h => Some(entity_action_msg_no_ecs!(
+            ecs_data,
+            "<SUBJ> {} {fmt_unequip_names} to free both hands and {} the two-handed {fmt_equip_names} ({bonus_changes}).",
+            owner,
+            "unequip",
+            "wield"
+        )),
         (false, false) => Some(entity_action_msg_no_ecs!(
             ecs_data,
             "<SUBJ> {} {fmt_unequip_names} and {} {fmt_equip_names} ({bonus_changes}).",
@@ -590,6 +716,7 @@ impl<'a> System<'a> for ItemRemoveSystem {
         WriteStorage<'a, InBackpack>,
         WriteStorage<'a, Equipped>,
         WriteExpect<'a, GameLog>,
+        WriteStorage<'a, Viewshed>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -602,6 +729,7 @@ impl<'a> System<'a> for ItemRemoveSystem {
             mut backpack,
             mut equipped,
             mut log,
+            mut viewsheds,
         ) = data;
         (&entities, &wants_remove)
             .join()
@@ -610,6 +738,9 @@ impl<'a> System<'a> for ItemRemoveSystem {
                 backpack
                     .insert(to_remove.item, InBackpack { owner: entity })
                     .unwrap_or_else(|er| panic!("Unable to unequip item fully: {}", er));
+                if let Some(viewshed) = viewsheds.get_mut(entity) {
+                    viewshed.dirty = true;
+                }
                 let item_name = names
                     .get(to_remove.item)
                     .map(|n| n.name.clone())
@@ -739,4 +870,125 @@ mod tests {
             .iter()
             .any(|e| e.contains(&expected_equip_submsg)));
     }
+
+    #[test]
+    fn two_rings_fill_both_ring_slots() {
+        let (mut gs, _) = init_state(true, None);
+        let player_entity = get_player_unwrap(&gs.ecs, PLAYER_NAME);
+        let player_posn = get_player_pos_unwrap(&gs.ecs, PLAYER_NAME);
+
+        let rings = (0..2)
+            .map(|_| {
+                let ring = spawner::spawn_named(&mut gs.ecs, "Ring", player_posn, 1).unwrap();
+                get_item(&mut gs.ecs); // pickup an item
+                gs.run_systems();
+                use_first_backpack_item(&mut gs, player_entity);
+                ring
+            })
+            .collect_vec();
+
+        assert!(backpack_items(&gs.ecs, player_entity).is_empty());
+        let equipped = gs.ecs.read_storage::<Equipped>();
+        let ring_slots: HashSet<EquipSlot> = rings
+            .iter()
+            .map(|ring| equipped.get(*ring).unwrap().slot.clone())
+            .collect();
+        assert_eq!(
+            ring_slots,
+            HashSet::from([EquipSlot::Ring, EquipSlot::SecondRing])
+        );
+    }
+
+    #[test]
+    fn two_handed_weapon_frees_both_hands() {
+        let (mut gs, _) = init_state(true, None);
+        let player_entity = get_player_unwrap(&gs.ecs, PLAYER_NAME);
+        let player_posn = get_player_pos_unwrap(&gs.ecs, PLAYER_NAME);
+
+        let mut pick_up_and_use = |name: &str| {
+            let item = spawner::spawn_named(&mut gs.ecs, name, player_posn, 1).unwrap();
+            get_item(&mut gs.ecs); // pickup an item
+            gs.run_systems();
+            use_first_backpack_item(&mut gs, player_entity);
+            item
+        };
+        let dagger = pick_up_and_use("Dagger");
+        let shield = pick_up_and_use("Shield");
+        let greatsword = pick_up_and_use("Greatsword");
+
+        let bpack_ents: HashSet<Entity> = backpack_items(&gs.ecs, player_entity)
+            .into_iter()
+            .map(|(ent, _)| ent)
+            .collect();
+        assert_eq!(bpack_ents, HashSet::from([dagger, shield]));
+        {
+            let log = gs.ecs.fetch::<GameLog>();
+            assert!(log.entries.iter().any(|e| e.contains("to free both hands")));
+        }
+
+        // Taking up the shield again puts the greatsword away entirely
+        {
+            let mut intent = gs.ecs.write_storage::<EventWantsToUseItem>();
+            intent
+                .insert(
+                    player_entity,
+                    EventWantsToUseItem {
+                        item: shield,
+                        target: None,
+                    },
+                )
+                .unwrap();
+        }
+        gs.run_systems();
+        let bpack_ents: HashSet<Entity> = backpack_items(&gs.ecs, player_entity)
+            .into_iter()
+            .map(|(ent, _)| ent)
+            .collect();
+        assert_eq!(bpack_ents, HashSet::from([dagger, greatsword]));
+    }
+
+    #[test]
+    fn monsters_cast_spells_without_using_them_up() {
+        let (mut gs, _) = init_state(true, None);
+        let player_entity = get_player_unwrap(&gs.ecs, PLAYER_NAME);
+        let player_posn = get_player_pos_unwrap(&gs.ecs, PLAYER_NAME);
+
+        let shaman = spawner::spawn_named(&mut gs.ecs, "Goblin Shaman", player_posn, 1).unwrap();
+        // Out of the way of its own spell
+        gs.ecs.write_storage::<Position>().remove(shaman);
+        let missile = {
+            let entities = gs.ecs.entities();
+            let spells = gs.ecs.read_storage::<Spell>();
+            let names = gs.ecs.read_storage::<Name>();
+            (&entities, &spells, &names)
+                .join()
+                .find(|(_, spell, name)| spell.caster == shaman && name.name == "Magic Missile")
+                .map(|(ent, _, _)| ent)
+                .unwrap()
+        };
+        let cast = |gs: &mut State| {
+            gs.ecs
+                .write_storage::<EventWantsToUseItem>()
+                .insert(
+                    shaman,
+                    EventWantsToUseItem {
+                        item: missile,
+                        target: Some(player_posn.into()),
+                    },
+                )
+                .unwrap();
+            gs.run_systems();
+        };
+        cast(&mut gs);
+        cast(&mut gs);
+
+        let stats = gs.ecs.read_storage::<CombatStats>();
+        assert_eq!(stats.get(player_entity).unwrap().hp, 30 - 2 * 8);
+        assert!(gs.ecs.entities().is_alive(missile));
+        let log = gs.ecs.fetch::<GameLog>();
+        assert!(log
+            .entries
+            .iter()
+            .any(|e| e == "Goblin Shaman casts Magic Missile, inflicting 8 damage on Player."));
+    }
 }
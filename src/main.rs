@@ -11,29 +11,46 @@ use bracket_lib::{
     random::RandomNumberGenerator,
     terminal::console,
 };
+use initiative_system::InitiativeSystem;
 use inventory_system::{ItemCollectionSystem, ItemDropSystem, ItemRemoveSystem, ItemUseSystem};
 use itertools::Itertools;
 use map_indexing_system::MapIndexingSystem;
-use spawner::spawn_room;
+use ranged_combat_system::RangedCombatSystem;
+use spawner::spawn_region;
 use specs::saveload::SimpleMarker;
 use specs::{prelude::*, saveload::SimpleMarkerAllocator};
+use status_effect_system::StatusEffectSystem;
 
+mod ai_pathing;
+mod combat;
 mod components;
 mod damage_system;
 mod display_state;
+mod dungeon;
 mod equipment;
+mod experience;
+mod faction;
 mod gamelog;
 mod gui;
+mod initiative_system;
 mod inventory_system;
 mod map;
+mod map_builders;
 mod map_indexing_system;
 mod melee_combat_system;
 mod monster_ai_system;
+mod morale;
 mod player;
 mod random_table;
+mod ranged_combat_system;
+mod raws;
 mod rect;
+mod run_info;
+mod save_migrations;
+mod save_storage;
 mod saveload_system;
 mod spawner;
+mod status_effect_system;
 mod util;
 mod util_ecs;
 mod visibility_system;
@@ -41,11 +58,13 @@ mod visibility_system;
 use components::*;
 use damage_system::*;
 use display_state::*;
+use dungeon::MasterDungeonMap;
 use gui::*;
 use map::*;
 use melee_combat_system::*;
 use monster_ai_system::*;
 use player::*;
+use save_storage::PlatformStorage;
 use visibility_system::VisibilitySystem;
 
 pub type PsnU = u16;
@@ -55,7 +74,8 @@ pub enum RunState {
     AwaitingInput,
     PreRun,
     PlayerTurn,
-    MonsterTurn,
+    /// The scheduler gives out energy until someone can act
+    Ticking,
     ShowInventory,
     ShowDropItem,
     ShowTargeting {
@@ -63,12 +83,24 @@ pub enum RunState {
         item: Entity,
     },
     ShowRemoveItem,
+    ShowFiring {
+        range: u16,
+    },
     MainMenu {
         menu_selection: gui::MainMenuSelection,
     },
+    NewGameMenu {
+        options: gui::NewGameOptions,
+    },
     KeyBindingsMenu,
-    SaveGame,
+    SaveSlotMenu {
+        mode: gui::SaveSlotMode,
+        selection: usize,
+        confirm: Option<gui::SlotConfirm>,
+    },
     NextLevel,
+    PreviousLevel,
+    LevelUp,
     GameOver,
 }
 
@@ -87,6 +119,10 @@ impl State {
     fn run_systems(&mut self) {
         // These systems are required to be mutable by run_now, but
         // there seems to be nothing to mutate (so far)
+        let mut initiative = InitiativeSystem {};
+        initiative.run_now(&self.ecs);
+        let mut status = StatusEffectSystem {};
+        status.run_now(&self.ecs);
         let mut vis = VisibilitySystem {};
         vis.run_now(&self.ecs);
         let mut mob = MonsterAI {};
@@ -95,6 +131,8 @@ impl State {
         map_index.run_now(&self.ecs);
         let mut melee = MeleeCombatSystem {};
         melee.run_now(&self.ecs);
+        let mut ranged = RangedCombatSystem {};
+        ranged.run_now(&self.ecs);
         let mut damage = DamageSystem {};
         damage.run_now(&self.ecs);
         let mut pickup = ItemCollectionSystem {};
@@ -109,44 +147,52 @@ impl State {
         self.ecs.maintain();
     }
 
-    fn entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
-        let entities = self.ecs.entities();
-        let player_entity = get_player_unwrap(&self.ecs, PLAYER_NAME);
-        let player_items = owned_items(&self.ecs, player_entity)
-            .iter()
-            .map(|it| it.0)
-            .collect_vec();
-        entities
-            .join()
-            .filter(|en| *en != player_entity && !player_items.contains(en))
-            .collect()
-    }
-
-    fn goto_next_level(&mut self) {
-        // remove all entities except player and player items
-        self.entities_to_remove_on_level_change()
-            .iter()
-            .for_each(|en| {
-                self.ecs.delete_entity(*en).unwrap_or_else(|er| {
-                    panic!("Failed to delete entity {:?}: {:?}", en, er);
-                });
-            });
-
-        // Build a new map and place the player
-        let (worldmap, current_depth) = {
-            let mut worldmap_resource = self.ecs.write_resource::<Map>();
-            let current_depth = worldmap_resource.depth;
-            *worldmap_resource = new_map_rooms_and_corridors(self, current_depth + 1);
-            (worldmap_resource.clone(), current_depth) // TODO: do we have to clone?
+    /// Leaves the current level, which is kept as it is, for the one `offset` levels down.
+    /// A level is generated the first time it is visited.
+    fn goto_level(&mut self, offset: i32) {
+        dungeon::freeze_level_entities(&self.ecs);
+        let new_depth = {
+            let map = self.ecs.fetch::<Map>();
+            self.ecs
+                .write_resource::<MasterDungeonMap>()
+                .store_map(&map);
+            map.depth + offset
         };
 
-        // Spawn monsters and items
-        worldmap.rooms.iter().skip(1).for_each(|room| {
-            spawn_room(&mut self.ecs, room, current_depth + 1);
-        });
+        let stored_map = self.ecs.fetch::<MasterDungeonMap>().get_map(new_depth);
+        let (player_pos_new, first_visit) = match stored_map {
+            Some(map) => {
+                // Arrive on the stairs that lead back the way the player came
+                let arrival_tile = if offset > 0 {
+                    TileType::UpStairs
+                } else {
+                    TileType::DownStairs
+                };
+                let arrival_pos = map
+                    .tiles
+                    .iter()
+                    .position(|tile| *tile == arrival_tile)
+                    .map(|ix| map.idx_to_pos(ix))
+                    .unwrap_or_else(|| panic!("No stairs to arrive on at depth {}", new_depth));
+                *self.ecs.write_resource::<Map>() = map;
+                dungeon::thaw_level_entities(&self.ecs, new_depth);
+                (arrival_pos, false)
+            }
+            None => {
+                let builder = map_builders::build_random_map(&self.ecs, &self.display, new_depth);
+                let start_pos = builder.get_starting_position();
+                let mut map = builder.get_map();
+                let start_ix = map.pos_idx(start_pos);
+                map.tiles[start_ix] = TileType::UpStairs;
+                *self.ecs.write_resource::<Map>() = map;
+
+                // Spawn monsters and items
+                populate_regions(&mut self.ecs, &builder.get_spawn_regions(), new_depth);
+                (start_pos, true)
+            }
+        };
 
         // Place the player and update related resources; set viewshed to dirty
-        let player_pos_new = worldmap.rooms[0].center();
         let mut positions = self.ecs.write_storage::<Position>();
         let player_entity = get_player_unwrap(&self.ecs, PLAYER_NAME);
         if let Some(player_pos) = positions.get_mut(player_entity) {
@@ -157,8 +203,14 @@ impl State {
             player_vs.dirty = true;
         }
 
-        // Notify the player and give them some health
         let mut gamelog = self.ecs.fetch_mut::<gamelog::GameLog>();
+        if !first_visit {
+            gamelog
+                .entries
+                .push(format!("You return to depth {}.", new_depth));
+            return;
+        }
+        // Notify the player and give them some health
         gamelog
             .entries
             .push("You descend to the next level, and take a moment to heal.".to_string());
@@ -170,6 +222,37 @@ impl State {
             );
         }
     }
+
+    fn autosave(&mut self) {
+        let run_info = self.ecs.fetch::<run_info::RunInfo>().clone();
+        if !run_info.autosave {
+            return;
+        }
+        let mut storage = PlatformStorage::default();
+        let message = match run_info
+            .save_slot
+            .or_else(|| saveload_system::first_free_slot(&storage))
+        {
+            None => "There is no free save slot to autosave to.".to_string(),
+            Some(slot) => match saveload_system::save_game(&mut self.ecs, &mut storage, slot) {
+                Ok(()) => format!("Autosaved to slot {}.", slot + 1),
+                Err(er) => format!("Autosave failed: {}", er),
+            },
+        };
+        self.ecs
+            .fetch_mut::<gamelog::GameLog>()
+            .entries
+            .push(message);
+    }
+
+    /// A permadeath run's save dies with the player.
+    fn forfeit_permadeath_save(&mut self) {
+        let run_info = self.ecs.fetch::<run_info::RunInfo>().clone();
+        if let (true, Some(slot)) = (run_info.permadeath, run_info.save_slot) {
+            // Fails harmlessly if loading the save already used it up
+            let _ = saveload_system::delete_save(&mut PlatformStorage::default(), slot);
+        }
+    }
 }
 
 fn remove_or_drop(state: &State, ctx: &mut BTerm, newrunstate: &mut RunState, mode: InventoryMode) {
@@ -229,12 +312,17 @@ impl GameState for State {
         ctx.cls();
 
         newrunstate = match newrunstate {
-            RunState::MainMenu { .. } => newrunstate,
+            RunState::MainMenu { .. }
+            | RunState::NewGameMenu { .. }
+            | RunState::SaveSlotMenu { .. } => newrunstate,
             _ => {
                 draw_map(&self.ecs, ctx);
                 draw_ui(&self.ecs, ctx, &self.display);
 
                 let game_over_opt = delete_the_dead(&mut self.ecs);
+                if game_over_opt.is_some() {
+                    self.forfeit_permadeath_save();
+                }
 
                 {
                     // draw renderables
@@ -250,6 +338,9 @@ impl GameState for State {
                             ctx.set(pos.xx, pos.yy, render.fg, render.bg, render.glyph);
                         });
                 }
+                if self.display.ai_overlay {
+                    draw_ai_overlay(&self.ecs, ctx);
+                }
                 game_over_opt.unwrap_or(newrunstate)
             }
         };
@@ -259,16 +350,25 @@ impl GameState for State {
                 self.ecs.maintain();
                 newrunstate = RunState::AwaitingInput;
             }
-            RunState::AwaitingInput => newrunstate = player_input(self, ctx),
+            RunState::AwaitingInput => {
+                let player_entity = get_player_unwrap(&self.ecs, PLAYER_NAME);
+                newrunstate = if experience::has_pending_level_up(&self.ecs, player_entity) {
+                    RunState::LevelUp
+                } else {
+                    player_input(self, ctx)
+                }
+            }
             RunState::PlayerTurn => {
                 self.run_systems();
                 self.ecs.maintain();
-                newrunstate = RunState::MonsterTurn;
+                self.ecs.write_resource::<run_info::RunInfo>().turns += 1;
+                newrunstate = RunState::Ticking;
             }
-            RunState::MonsterTurn => {
+            RunState::Ticking => {
+                // Whoever is next acts; the scheduler switches to input on the player's turn
                 self.run_systems();
                 self.ecs.maintain();
-                newrunstate = RunState::AwaitingInput;
+                newrunstate = *self.ecs.fetch::<RunState>();
             }
             // TODO: consider abstracting the next 3 into a single function ... but probably not worth it
             RunState::ShowInventory => {
@@ -313,6 +413,17 @@ impl GameState for State {
                     }
                 }
             }
+            RunState::LevelUp => {
+                if let Some(gain) = gui::level_up_menu(self, ctx) {
+                    let player_entity = get_player_unwrap(&self.ecs, PLAYER_NAME);
+                    experience::apply_level_up(&self.ecs, player_entity, gain);
+                    self.ecs
+                        .fetch_mut::<gamelog::GameLog>()
+                        .entries
+                        .push(format!("You gain {}.", gain.describe()));
+                    newrunstate = RunState::AwaitingInput;
+                }
+            }
             RunState::ShowDropItem => {
                 remove_or_drop(self, ctx, &mut newrunstate, InventoryMode::Drop)
             }
@@ -348,6 +459,25 @@ impl GameState for State {
                     }
                 }
             }
+            RunState::ShowFiring { range } => {
+                let result = gui::ranged_target(self, ctx, range);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        if let Some(target) = result.1 {
+                            self.ecs
+                                .write_storage::<EventWantsToShoot>()
+                                .insert(
+                                    get_player_unwrap(&self.ecs, PLAYER_NAME),
+                                    EventWantsToShoot { target },
+                                )
+                                .unwrap_or_else(|er| panic!("Unable to take aim: {}", er));
+                        }
+                        newrunstate = RunState::PlayerTurn;
+                    }
+                }
+            }
             RunState::MainMenu { .. } => {
                 let result = gui::main_menu(self, ctx);
                 match result.status {
@@ -356,33 +486,63 @@ impl GameState for State {
                             menu_selection: result.highlighted,
                         };
                     }
-                    gui::MainMenuStatus::Selected => match result.highlighted {
-                        gui::MainMenuSelection::NewGame => {
-                            delete_state(&mut self.ecs);
-                            (*self, _) = init_state(false, Some(ctx));
-                            newrunstate = RunState::PreRun
-                        }
-                        gui::MainMenuSelection::SaveGame => newrunstate = RunState::SaveGame,
-                        gui::MainMenuSelection::ResumeGame => newrunstate = RunState::PreRun,
-                        gui::MainMenuSelection::LoadGame => {
-                            if saveload_system::does_save_exist() {
-                                saveload_system::load_game(&mut self.ecs);
-                                newrunstate = RunState::AwaitingInput;
-                            } else {
-                                let mut gamelog = self.ecs.fetch_mut::<gamelog::GameLog>();
-                                gamelog
-                                    .entries
-                                    .push("No save game to load. Starting new game!".to_string());
-                                newrunstate = RunState::PreRun
+                    gui::MainMenuStatus::Selected => {
+                        self.ecs.write_resource::<MenuNotice>().text = None;
+                        match result.highlighted {
+                            gui::MainMenuSelection::NewGame => {
+                                newrunstate = RunState::NewGameMenu {
+                                    options: gui::NewGameOptions {
+                                        seed: run_info::initial_seed(),
+                                        autosave: false,
+                                        permadeath: false,
+                                    },
+                                };
                             }
+                            gui::MainMenuSelection::SaveGame => {
+                                newrunstate =
+                                    open_save_slot_menu(&mut self.ecs, SaveSlotMode::Save);
+                            }
+                            gui::MainMenuSelection::ResumeGame => newrunstate = RunState::PreRun,
+                            gui::MainMenuSelection::LoadGame => {
+                                if saveload_system::does_save_exist(&PlatformStorage::default()) {
+                                    newrunstate =
+                                        open_save_slot_menu(&mut self.ecs, SaveSlotMode::Load);
+                                } else {
+                                    let mut gamelog = self.ecs.fetch_mut::<gamelog::GameLog>();
+                                    gamelog.entries.push(
+                                        "No save game to load. Starting new game!".to_string(),
+                                    );
+                                    newrunstate = RunState::PreRun
+                                }
+                            }
+                            gui::MainMenuSelection::KeyBindings => {
+                                newrunstate = RunState::KeyBindingsMenu;
+                            }
+                            gui::MainMenuSelection::Quit => ctx.quit(),
                         }
-                        gui::MainMenuSelection::KeyBindings => {
-                            newrunstate = RunState::KeyBindingsMenu;
-                        }
-                        gui::MainMenuSelection::Quit => ctx.quit(),
-                    },
+                    }
                 }
             }
+            RunState::NewGameMenu { options } => match gui::new_game_menu(ctx, options) {
+                gui::NewGameMenuResult::NoSelection { options } => {
+                    newrunstate = RunState::NewGameMenu { options };
+                }
+                gui::NewGameMenuResult::Cancel => {
+                    newrunstate = RunState::MainMenu {
+                        menu_selection: gui::MainMenuSelection::NewGame,
+                    }
+                }
+                gui::NewGameMenuResult::Start { options } => {
+                    delete_state(&mut self.ecs);
+                    (*self, _) = init_state_seeded(false, Some(ctx), options.seed);
+                    {
+                        let mut run_info = self.ecs.write_resource::<run_info::RunInfo>();
+                        run_info.autosave = options.autosave;
+                        run_info.permadeath = options.permadeath;
+                    }
+                    newrunstate = RunState::PreRun
+                }
+            },
             RunState::KeyBindingsMenu => match gui::show_keybindings(self, ctx) {
                 true => newrunstate = RunState::KeyBindingsMenu,
                 false => {
@@ -391,14 +551,67 @@ impl GameState for State {
                     }
                 }
             },
-            RunState::SaveGame => {
-                saveload_system::save_game(&mut self.ecs);
-                newrunstate = RunState::MainMenu {
-                    menu_selection: gui::MainMenuSelection::SaveGame,
-                }
+            RunState::SaveSlotMenu {
+                mode,
+                selection,
+                confirm,
+            } => {
+                let menu_selection = match mode {
+                    SaveSlotMode::Save => gui::MainMenuSelection::SaveGame,
+                    SaveSlotMode::Load => gui::MainMenuSelection::LoadGame,
+                };
+                newrunstate = match gui::save_slot_menu(self, ctx, mode, selection, confirm) {
+                    gui::SaveSlotMenuResult::NoSelection { selection, confirm } => {
+                        RunState::SaveSlotMenu {
+                            mode,
+                            selection,
+                            confirm,
+                        }
+                    }
+                    gui::SaveSlotMenuResult::Cancel => RunState::MainMenu { menu_selection },
+                    gui::SaveSlotMenuResult::Save(slot) => {
+                        if let Err(er) = saveload_system::save_game(
+                            &mut self.ecs,
+                            &mut PlatformStorage::default(),
+                            slot,
+                        ) {
+                            self.ecs.write_resource::<MenuNotice>().text =
+                                Some(format!("Unable to save: {}", er));
+                        }
+                        RunState::MainMenu { menu_selection }
+                    }
+                    gui::SaveSlotMenuResult::Load(slot) => {
+                        match saveload_system::load_game(
+                            &mut self.ecs,
+                            &mut PlatformStorage::default(),
+                            slot,
+                        ) {
+                            Ok(()) => RunState::AwaitingInput,
+                            Err(er) => {
+                                self.ecs.write_resource::<MenuNotice>().text =
+                                    Some(format!("Unable to load: {}", er));
+                                RunState::MainMenu { menu_selection }
+                            }
+                        }
+                    }
+                    gui::SaveSlotMenuResult::Delete(slot) => {
+                        if let Err(er) =
+                            saveload_system::delete_save(&mut PlatformStorage::default(), slot)
+                        {
+                            self.ecs.write_resource::<MenuNotice>().text =
+                                Some(format!("Unable to delete: {}", er));
+                        }
+                        open_save_slot_menu(&mut self.ecs, mode)
+                    }
+                };
             }
             RunState::NextLevel => {
-                self.goto_next_level();
+                self.goto_level(1);
+                self.autosave();
+                newrunstate = RunState::PreRun;
+            }
+            RunState::PreviousLevel => {
+                self.goto_level(-1);
                 newrunstate = RunState::PreRun;
             }
             RunState::GameOver => {
@@ -419,6 +632,18 @@ impl GameState for State {
     }
 }
 
+/// Rescans the save slots, since they may have changed on disk since the menu last opened.
+fn open_save_slot_menu(ecs: &mut World, mode: SaveSlotMode) -> RunState {
+    ecs.insert(saveload_system::SaveSlots::scan(
+        &mut PlatformStorage::default(),
+    ));
+    RunState::SaveSlotMenu {
+        mode,
+        selection: 0,
+        confirm: None,
+    }
+}
+
 pub fn delete_state(ecs: &mut World) {
     // Delete everything
     let to_delete: Vec<Entity> = ecs.entities().join().collect();
@@ -429,6 +654,15 @@ pub fn delete_state(ecs: &mut World) {
 }
 
 pub fn init_state(test_ecs: bool, ctxt_opt: Option<&BTerm>) -> (State, Option<BTerm>) {
+    init_state_seeded(test_ecs, ctxt_opt, run_info::initial_seed())
+}
+
+/// Everything random in a run (maps, spawns, combat) draws from the one RNG seeded here.
+pub fn init_state_seeded(
+    test_ecs: bool,
+    ctxt_opt: Option<&BTerm>,
+    seed: u64,
+) -> (State, Option<BTerm>) {
     let (mut gs, opt_ctxt) = if test_ecs {
         (
             State {
@@ -468,6 +702,8 @@ pub fn init_state(test_ecs: bool, ctxt_opt: Option<&BTerm>) -> (State, Option<BT
     };
 
     execute_with_type_list!(register_individually!(gs.ecs));
+    // Never saved
+    gs.ecs.register::<MyTurn>();
 
     // register markers
     gs.ecs.register::<SimpleMarker<SerializeMe>>();
@@ -478,14 +714,20 @@ pub fn init_state(test_ecs: bool, ctxt_opt: Option<&BTerm>) -> (State, Option<BT
     });
     gs.ecs.insert(gamelog::GameLog {
         entries: vec!["Welcome to Rusty Rogue!".to_string()],
+        verbose: false,
     });
-    gs.ecs.insert(RandomNumberGenerator::new());
+    gs.ecs.insert(RandomNumberGenerator::seeded(seed));
+    gs.ecs.insert(MenuNotice::default());
+    gs.ecs.insert(MasterDungeonMap::default());
+    gs.ecs.insert(ai_pathing::AiPathing::default());
+    gs.ecs.insert(saveload_system::SaveSlots::default());
+    gs.ecs.insert(run_info::RunInfo::new(seed));
 
-    let map = new_map_rooms_and_corridors(&gs, 1);
+    let builder = map_builders::build_random_map(&gs.ecs, &gs.display, 1);
 
-    let player_posn = map.rooms.first().unwrap().center();
-    gs.ecs.insert(map);
-    populate_rooms(&mut gs.ecs);
+    let player_posn = builder.get_starting_position();
+    gs.ecs.insert(builder.get_map());
+    populate_regions(&mut gs.ecs, &builder.get_spawn_regions(), 1);
 
     // FIXME: unit discard warning?
     spawner::player(&mut gs, player_posn);
@@ -501,6 +743,12 @@ fn main() {
         // Globals
         let default_keys = KeyBindings::_make_default();
         DEFAULT_KEY_BINDINGS.set(default_keys).unwrap();
+        if let Some(seed) = run_info::parse_seed_arg(std::env::args().skip(1)) {
+            run_info::CLI_SEED.set(seed).unwrap();
+        }
+        if let Err(err) = raws::load_raws() {
+            panic!("{}", err);
+        }
     }
     if let (gs, Some(context)) = init_state(false, None) {
         bracket_lib::prelude::main_loop(context, gs).unwrap()
@@ -509,14 +757,9 @@ fn main() {
     }
 }
 
-fn populate_rooms(ecs: &mut World) -> Vec<Entity> {
-    let rooms = {
-        let map = ecs.read_resource::<Map>();
-        map.rooms.clone()
-    };
-    rooms
+fn populate_regions(ecs: &mut World, regions: &[Vec<usize>], map_depth: i32) -> Vec<Entity> {
+    regions
         .iter()
-        .skip(1)
-        .flat_map(|room| spawn_room(ecs, room, 1))
+        .flat_map(|region| spawn_region(ecs, region, map_depth))
         .collect()
 }
@@ -1,5 +1,4 @@
 use bracket_lib::prelude::{Algorithm2D, BaseMap};
-use bracket_lib::random::RandomNumberGenerator;
 use bracket_lib::terminal::{to_cp437, BTerm, DistanceAlg, FontCharType, Point, RGB};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -7,7 +6,7 @@ use specs::*;
 use std::cmp::{max, min};
 
 use crate::components::{xy_idx, Positionable};
-use crate::{Position, PsnU, State};
+use crate::{Position, PsnU};
 
 use crate::rect::*;
 
@@ -18,6 +17,7 @@ pub enum TileType {
     Wall,
     Floor,
     DownStairs,
+    UpStairs,
 }
 
 /// Makes a map with solid boundaries and randomly placed walls
@@ -74,6 +74,7 @@ pub fn draw_map(ecs: &World, ctx: &mut BTerm) {
                     (RGB::from_f32(0., 1.0, 0.), glyph)
                 }
                 TileType::DownStairs => (RGB::from_f32(0.33, 0.60, 0.35), to_cp437('>')),
+                TileType::UpStairs => (RGB::from_f32(0.33, 0.60, 0.35), to_cp437('<')),
             };
             let fg = if !map.visible_tiles[ix] {
                 fg.to_greyscale()
@@ -122,6 +123,30 @@ pub fn idx_to_pos(width: usize, ix: usize) -> Position {
 }
 
 impl Map {
+    /// Creates a map of solid walls; map builders carve it out from there.
+    pub fn new(width: usize, height: usize, depth: i32) -> Map {
+        let tile_count = width * height;
+        Map {
+            tiles: vec![TileType::Wall; tile_count],
+            rooms: Vec::new(),
+            width,
+            height,
+            tile_count,
+            width_psnu: width.try_into().unwrap(),
+            height_psnu: height.try_into().unwrap(),
+            revealed_tiles: vec![false; tile_count],
+            visible_tiles: vec![false; tile_count],
+            blocked: vec![false; tile_count],
+            depth,
+
+            // The map_indexing system already visits each tile in the map to looking for blocking tiles
+            // so we can instead alter that scan to populate
+            // which entities are at each tile, preventing us from having to iterate over the join of all
+            // entities and positions again. We store the result in `tile_content`.
+            tile_content: vec![Vec::new(); tile_count],
+        }
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -155,7 +180,7 @@ impl Map {
         }
     }
 
-    fn add_room(self: &mut Map, room: &Rect) {
+    pub fn add_room(self: &mut Map, room: &Rect) {
         (room.x1..=room.x2)
             .cartesian_product(room.y1..=room.y2)
             .for_each(|(xx, yy)| {
@@ -163,7 +188,7 @@ impl Map {
             })
     }
 
-    fn add_horizontal_tunnel(self: &mut Map, x1: PsnU, x2: PsnU, yy: PsnU) {
+    pub fn add_horizontal_tunnel(self: &mut Map, x1: PsnU, x2: PsnU, yy: PsnU) {
         (min(x1, x2)..=max(x1, x2)).for_each(|xx| {
             let ix = self.xy_idx(xx, yy);
             if ix > 0 && ix < (self.tile_count) {
@@ -172,7 +197,7 @@ impl Map {
         })
     }
 
-    fn add_vertical_tunnel(self: &mut Map, y1: PsnU, y2: PsnU, xx: PsnU) {
+    pub fn add_vertical_tunnel(self: &mut Map, y1: PsnU, y2: PsnU, xx: PsnU) {
         (min(y1, y2)..=max(y1, y2)).for_each(|yy| {
             let ix = self.xy_idx(xx, yy);
             if ix > 0 && ix < (self.tile_count) {
@@ -281,72 +306,6 @@ impl Algorithm2D for Map {
         Point::new(self.width_psnu, self.height_psnu)
     }
 }
-pub fn new_map_rooms_and_corridors(gs: &State, new_depth: i32) -> Map {
-    let map_width = gs.display.width.try_into().unwrap();
-    let display_height: usize = gs.display.height.try_into().unwrap();
-    let map_height = display_height - crate::gui::PANEL_HEIGHT;
-    let map_tile_count: usize = map_width * map_height;
-    let mut map = Map {
-        tiles: vec![TileType::Wall; map_tile_count],
-        rooms: Vec::new(),
-        width: map_width,
-        height: map_height,
-        tile_count: map_tile_count,
-        width_psnu: gs.display.width,
-        height_psnu: gs.display.height,
-        revealed_tiles: vec![false; map_tile_count],
-        visible_tiles: vec![false; map_tile_count],
-        blocked: vec![false; map_tile_count],
-        depth: new_depth,
-
-        // The map_indexing system already visits each tile in the map to looking for blocking tiles
-        // so we can instead alter that scan to populate
-        // which entities are at each tile, preventing us from having to iterate over the join of all
-        // entities and positions again. We store the result in `tile_content`.
-        tile_content: vec![Vec::new(); map_tile_count],
-    };
-
-    const MAX_ROOMS: u16 = 30;
-    const MIN_SIZE: PsnU = 6;
-    const MAX_SIZE: PsnU = 10;
-
-    let mut rng = gs.ecs.write_resource::<RandomNumberGenerator>();
-
-    (0..MAX_ROOMS).for_each(|_| {
-        let ww = rng.range(MIN_SIZE, MAX_SIZE);
-        let hh = rng.range(MIN_SIZE, MAX_SIZE);
-        let xx = rng.range(1, map_width as u16 - ww - 1);
-        let yy = rng.range(1, map_height as u16 - hh - 1);
-
-        let new_room = Rect::new(xx, yy, ww, hh);
-
-        let room_ok = map
-            .rooms
-            .iter()
-            .all(|other_room| !new_room.intersect(other_room));
-        if room_ok {
-            map.add_room(&new_room);
-            if let Some(prev_room) = map.rooms.last() {
-                let new_center = new_room.center();
-                let pre_center = prev_room.center();
-                if rng.range(0, 2) == 1 {
-                    map.add_horizontal_tunnel(pre_center.xx, new_center.xx, pre_center.yy);
-                    map.add_vertical_tunnel(pre_center.yy, new_center.yy, new_center.xx);
-                } else {
-                    map.add_vertical_tunnel(pre_center.yy, new_center.yy, pre_center.xx);
-                    map.add_horizontal_tunnel(pre_center.xx, new_center.xx, new_center.yy);
-                }
-            }
-            map.rooms.push(new_room)
-        }
-    });
-
-    let stairs_pos = map.rooms[map.rooms.len() - 1].center();
-    let stairs_ix = map.pos_idx(stairs_pos);
-    map.tiles[stairs_ix] = TileType::DownStairs;
-
-    map
-}
 
 fn is_revealed_and_wall(map: &Map, xx_opt: Option<PsnU>, yy_opt: Option<PsnU>) -> bool {
     let default_return = false; // If it is off screen, it isn't revealed
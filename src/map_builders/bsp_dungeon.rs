@@ -0,0 +1,152 @@
+use bracket_lib::random::RandomNumberGenerator;
+use itertools::Itertools;
+
+use super::{
+    common::{room_spawn_regions, room_stairs_ix},
+    MapBuilder,
+};
+use crate::{
+    map::{Map, TileType},
+    rect::Rect,
+    Position, PsnU,
+};
+
+const MAX_SPLIT_ATTEMPTS: u16 = 240;
+const ROOM_PADDING: i32 = 2;
+
+/// Binary space partitioning: the map is recursively split into quarters, a room is
+/// placed inside some of the leaves, and neighbouring rooms are joined by corridors.
+pub struct BspDungeonBuilder {
+    map: Map,
+    rects: Vec<Rect>,
+}
+
+impl BspDungeonBuilder {
+    pub fn new(width: usize, height: usize, new_depth: i32) -> BspDungeonBuilder {
+        BspDungeonBuilder {
+            map: Map::new(width, height, new_depth),
+            rects: Vec::new(),
+        }
+    }
+
+    fn add_subrects(&mut self, rect: &Rect) {
+        let half_width = PsnU::max((rect.x2 - rect.x1) / 2, 1);
+        let half_height = PsnU::max((rect.y2 - rect.y1) / 2, 1);
+
+        self.rects
+            .push(Rect::new(rect.x1, rect.y1, half_width, half_height));
+        self.rects.push(Rect::new(
+            rect.x1,
+            rect.y1 + half_height,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Rect::new(
+            rect.x1 + half_width,
+            rect.y1,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Rect::new(
+            rect.x1 + half_width,
+            rect.y1 + half_height,
+            half_width,
+            half_height,
+        ));
+    }
+
+    fn random_sub_rect(rect: &Rect, rng: &mut RandomNumberGenerator) -> Rect {
+        let rect_width = i32::from(rect.x2 - rect.x1).max(1);
+        let rect_height = i32::from(rect.y2 - rect.y1).max(1);
+        let ww = i32::max(3, rng.roll_dice(1, i32::min(rect_width, 10)) - 1) + 1;
+        let hh = i32::max(3, rng.roll_dice(1, i32::min(rect_height, 10)) - 1) + 1;
+        let xx = i32::from(rect.x1) + rng.roll_dice(1, 6) - 1;
+        let yy = i32::from(rect.y1) + rng.roll_dice(1, 6) - 1;
+        Rect::new(
+            xx.try_into().unwrap(),
+            yy.try_into().unwrap(),
+            ww.try_into().unwrap(),
+            hh.try_into().unwrap(),
+        )
+    }
+
+    /// A room fits if it and its padding stay inside the map and only overlap walls.
+    fn is_possible(&self, rect: &Rect) -> bool {
+        let x1 = i32::from(rect.x1) - ROOM_PADDING;
+        let x2 = i32::from(rect.x2) + ROOM_PADDING;
+        let y1 = i32::from(rect.y1) - ROOM_PADDING;
+        let y2 = i32::from(rect.y2) + ROOM_PADDING;
+        let max_x = self.map.width() as i32 - 2;
+        let max_y = self.map.height() as i32 - 2;
+        (x1..=x2).cartesian_product(y1..=y2).all(|(xx, yy)| {
+            xx >= 1
+                && yy >= 1
+                && xx <= max_x
+                && yy <= max_y
+                && self.map.tiles[self.map.xy_idx(xx as PsnU, yy as PsnU)] == TileType::Wall
+        })
+    }
+
+    fn add_corridor(&mut self, from: Position, to: Position) {
+        self.map.add_horizontal_tunnel(from.xx, to.xx, from.yy);
+        self.map.add_vertical_tunnel(from.yy, to.yy, to.xx);
+    }
+}
+
+impl MapBuilder for BspDungeonBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        let whole_map = Rect::new(
+            2,
+            2,
+            self.map.width_psnu - 5,
+            self.map.height_psnu - 5,
+        );
+        self.rects.clear();
+        self.add_subrects(&whole_map);
+
+        (0..MAX_SPLIT_ATTEMPTS).for_each(|_| {
+            if let Some(rect) = rng.random_slice_entry(&self.rects).cloned() {
+                let candidate = Self::random_sub_rect(&rect, rng);
+                if self.is_possible(&candidate) {
+                    self.map.add_room(&candidate);
+                    self.map.rooms.push(candidate);
+                    self.add_subrects(&rect);
+                }
+            }
+        });
+
+        // Fall back to a single room, so there is always somewhere to stand
+        if self.map.rooms.is_empty() {
+            let room = Rect::new(
+                self.map.width_psnu / 2 - 3,
+                self.map.height_psnu / 2 - 3,
+                6,
+                6,
+            );
+            self.map.add_room(&room);
+            self.map.rooms.push(room);
+        }
+
+        self.map.rooms.sort_by_key(|room| room.x1);
+        let centers = self.map.rooms.iter().map(|room| room.center()).collect_vec();
+        centers
+            .iter()
+            .tuple_windows()
+            .for_each(|(from, to)| self.add_corridor(*from, *to));
+
+        let stairs_ix = room_stairs_ix(&mut self.map);
+        self.map.tiles[stairs_ix] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.map.rooms[0].center()
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        room_spawn_regions(&self.map)
+    }
+}
@@ -0,0 +1,99 @@
+use bracket_lib::random::RandomNumberGenerator;
+use itertools::Itertools;
+
+use super::{
+    common::{
+        center, chunked_spawn_regions, is_interior, nearest_floor,
+        remove_unreachable_areas_returning_most_distant,
+    },
+    MapBuilder,
+};
+use crate::{
+    map::{Map, TileType},
+    Position,
+};
+
+const INITIAL_FLOOR_PERCENT: i32 = 45;
+const SMOOTHING_ITERATIONS: u16 = 15;
+
+/// Organic caves: random noise smoothed by a "more than four wall neighbours" rule.
+pub struct CellularAutomataBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl CellularAutomataBuilder {
+    pub fn new(width: usize, height: usize, new_depth: i32) -> CellularAutomataBuilder {
+        CellularAutomataBuilder {
+            map: Map::new(width, height, new_depth),
+            starting_position: Position { xx: 0, yy: 0 },
+        }
+    }
+
+    fn wall_neighbours(&self, xx: i32, yy: i32) -> usize {
+        (-1..=1)
+            .cartesian_product(-1..=1)
+            .filter(|(dx, dy)| (*dx, *dy) != (0, 0))
+            .filter(|(dx, dy)| {
+                let ix = self.map.xy_idx((xx + dx) as u16, (yy + dy) as u16);
+                self.map.tiles[ix] == TileType::Wall
+            })
+            .count()
+    }
+
+    fn smooth(&mut self) {
+        let width = self.map.width() as i32;
+        let height = self.map.height() as i32;
+        let new_tiles = (0..self.map.tile_count())
+            .map(|ix| {
+                let pos = self.map.idx_to_pos(ix);
+                let (xx, yy) = (i32::from(pos.xx), i32::from(pos.yy));
+                if xx < 1 || yy < 1 || xx > width - 2 || yy > height - 2 {
+                    TileType::Wall
+                } else {
+                    match self.wall_neighbours(xx, yy) {
+                        0 => TileType::Wall,
+                        nn if nn > 4 => TileType::Wall,
+                        _ => TileType::Floor,
+                    }
+                }
+            })
+            .collect_vec();
+        self.map.tiles = new_tiles;
+    }
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        (0..self.map.tile_count()).for_each(|ix| {
+            let pos = self.map.idx_to_pos(ix);
+            if is_interior(&self.map, pos.xx.into(), pos.yy.into())
+                && rng.roll_dice(1, 100) <= INITIAL_FLOOR_PERCENT
+            {
+                self.map.tiles[ix] = TileType::Floor;
+            }
+        });
+
+        (0..SMOOTHING_ITERATIONS).for_each(|_| self.smooth());
+
+        let map_center = center(&self.map);
+        self.starting_position = nearest_floor(&mut self.map, map_center);
+        let stairs_ix = remove_unreachable_areas_returning_most_distant(
+            &mut self.map,
+            self.starting_position,
+        );
+        self.map.tiles[stairs_ix] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        chunked_spawn_regions(&self.map, self.starting_position)
+    }
+}
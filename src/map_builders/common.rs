@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use bracket_lib::prelude::DijkstraMap;
+use itertools::Itertools;
+
+use crate::{
+    map::{Map, TileType},
+    Position, PsnU,
+};
+
+/// Size of the square chunks used to split open maps (caves, mazes) into spawn regions.
+pub const REGION_CHUNK_SIZE: usize = 8;
+const MIN_REGION_TILES: usize = 4;
+
+/// Rooms double as spawn regions; the first room holds the player, so it is skipped.
+pub fn room_spawn_regions(map: &Map) -> Vec<Vec<usize>> {
+    map.rooms
+        .iter()
+        .skip(1)
+        .map(|room| {
+            (room.x1..=room.x2)
+                .cartesian_product(room.y1..=room.y2)
+                .map(|(xx, yy)| map.xy_idx(xx, yy))
+                .collect()
+        })
+        .collect()
+}
+
+/// Splits the floor of a map without rooms into square chunks. A `BTreeMap` keeps the
+/// region order (and therefore RNG consumption while spawning) stable.
+pub fn chunked_spawn_regions(map: &Map, start: Position) -> Vec<Vec<usize>> {
+    let chunk_of = |pos: Position| {
+        (
+            pos.xx as usize / REGION_CHUNK_SIZE,
+            pos.yy as usize / REGION_CHUNK_SIZE,
+        )
+    };
+    let start_chunk = chunk_of(start);
+    let mut regions: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+    map.tiles
+        .iter()
+        .enumerate()
+        .filter(|(_, tile)| **tile == TileType::Floor)
+        .for_each(|(ix, _)| {
+            let chunk = chunk_of(map.idx_to_pos(ix));
+            if chunk != start_chunk {
+                regions.entry(chunk).or_default().push(ix);
+            }
+        });
+    regions
+        .into_values()
+        .filter(|region| region.len() >= MIN_REGION_TILES)
+        .collect()
+}
+
+/// Returns the floor tile closest to `target`, turning `target` into floor if the
+/// map has no floor at all.
+pub fn nearest_floor(map: &mut Map, target: Position) -> Position {
+    let nearest = map
+        .tiles
+        .iter()
+        .enumerate()
+        .filter(|(_, tile)| **tile == TileType::Floor)
+        .map(|(ix, _)| map.idx_to_pos(ix))
+        .min_by_key(|pos| {
+            let dx = pos.xx.abs_diff(target.xx) as u32;
+            let dy = pos.yy.abs_diff(target.yy) as u32;
+            dx * dx + dy * dy
+        });
+    match nearest {
+        Some(pos) => pos,
+        None => {
+            let ix = map.pos_idx(target);
+            map.tiles[ix] = TileType::Floor;
+            target
+        }
+    }
+}
+
+/// Walls off every tile that can't be reached from `start`, and returns the index of the
+/// reachable floor tile that is furthest away (a good place for the stairs).
+pub fn remove_unreachable_areas_returning_most_distant(map: &mut Map, start: Position) -> usize {
+    let start_ix = map.pos_idx(start);
+    map.populate_blocked();
+    let dijkstra_map = DijkstraMap::new(map.width(), map.height(), &[start_ix], &*map, 1000.0);
+
+    let (most_distant_ix, _) = dijkstra_map
+        .map
+        .iter()
+        .enumerate()
+        .filter(|(ix, _)| map.tiles[*ix] == TileType::Floor)
+        .fold((start_ix, 0.0_f32), |(best_ix, best_dist), (ix, dist)| {
+            if *dist != f32::MAX && *dist > best_dist {
+                (ix, *dist)
+            } else {
+                (best_ix, best_dist)
+            }
+        });
+
+    let unreachable = dijkstra_map
+        .map
+        .iter()
+        .enumerate()
+        .filter(|(ix, dist)| **dist == f32::MAX && map.tiles[*ix] == TileType::Floor)
+        .map(|(ix, _)| ix)
+        .collect_vec();
+    unreachable.into_iter().for_each(|ix| {
+        map.tiles[ix] = TileType::Wall;
+    });
+    map.populate_blocked();
+
+    most_distant_ix
+}
+
+/// The stairs go in the middle of the last room, unless the player starts there because
+/// it is the only room; then they go on the floor furthest from the start.
+pub fn room_stairs_ix(map: &mut Map) -> usize {
+    let start = map.rooms[0].center();
+    let last_center = map.rooms[map.rooms.len() - 1].center();
+    if last_center == start {
+        remove_unreachable_areas_returning_most_distant(map, start)
+    } else {
+        map.pos_idx(last_center)
+    }
+}
+
+pub fn center(map: &Map) -> Position {
+    Position {
+        xx: (map.width() / 2).try_into().unwrap(),
+        yy: (map.height() / 2).try_into().unwrap(),
+    }
+}
+
+/// Keeps carving algorithms off the outer ring of the map, so the map stays walled in.
+pub fn is_interior(map: &Map, xx: i32, yy: i32) -> bool {
+    xx >= 1 && yy >= 1 && xx <= map.width() as i32 - 2 && yy <= map.height() as i32 - 2
+}
+
+pub fn interior_position(map: &Map, xx: i32, yy: i32) -> Option<Position> {
+    is_interior(map, xx, yy).then_some(Position {
+        xx: xx as PsnU,
+        yy: yy as PsnU,
+    })
+}
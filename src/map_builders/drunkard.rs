@@ -0,0 +1,110 @@
+use bracket_lib::random::RandomNumberGenerator;
+
+use super::{
+    common::{
+        center, chunked_spawn_regions, interior_position,
+        remove_unreachable_areas_returning_most_distant,
+    },
+    MapBuilder,
+};
+use crate::{
+    map::{Map, TileType},
+    Position,
+};
+
+const DESIRED_FLOOR_PERCENT: usize = 40;
+const DIGGER_LIFETIME: u16 = 400;
+const MAX_DIGGERS: u16 = 500;
+
+/// Wandering "drunken" diggers carve out tunnels until enough of the map is open.
+pub struct DrunkardsWalkBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new(width: usize, height: usize, new_depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder {
+            map: Map::new(width, height, new_depth),
+            starting_position: Position { xx: 0, yy: 0 },
+        }
+    }
+
+    fn floor_count(&self) -> usize {
+        self.map
+            .tiles
+            .iter()
+            .filter(|tile| **tile == TileType::Floor)
+            .count()
+    }
+
+    fn random_floor(&self, rng: &mut RandomNumberGenerator) -> Position {
+        let floor_tiles: Vec<usize> = self
+            .map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| **tile == TileType::Floor)
+            .map(|(ix, _)| ix)
+            .collect();
+        rng.random_slice_entry(&floor_tiles)
+            .map(|ix| self.map.idx_to_pos(*ix))
+            .unwrap_or(self.starting_position)
+    }
+
+    fn dig(&mut self, start: Position, rng: &mut RandomNumberGenerator) {
+        (0..DIGGER_LIFETIME).fold(start, |pos, _| {
+            let ix = self.map.pos_idx(pos);
+            self.map.tiles[ix] = TileType::Floor;
+            let (dx, dy) = match rng.roll_dice(1, 4) {
+                1 => (-1, 0),
+                2 => (1, 0),
+                3 => (0, -1),
+                _ => (0, 1),
+            };
+            interior_position(
+                &self.map,
+                i32::from(pos.xx) + dx,
+                i32::from(pos.yy) + dy,
+            )
+            .unwrap_or(pos)
+        });
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.starting_position = center(&self.map);
+        let desired_floor = self.map.tile_count() * DESIRED_FLOOR_PERCENT / 100;
+
+        // The first digger starts where the player will; later ones start anywhere already dug
+        let mut diggers = 0;
+        while self.floor_count() < desired_floor && diggers < MAX_DIGGERS {
+            let start = if diggers == 0 {
+                self.starting_position
+            } else {
+                self.random_floor(rng)
+            };
+            self.dig(start, rng);
+            diggers += 1;
+        }
+
+        let stairs_ix = remove_unreachable_areas_returning_most_distant(
+            &mut self.map,
+            self.starting_position,
+        );
+        self.map.tiles[stairs_ix] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        chunked_spawn_regions(&self.map, self.starting_position)
+    }
+}
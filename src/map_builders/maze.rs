@@ -0,0 +1,100 @@
+use bracket_lib::random::RandomNumberGenerator;
+
+use super::{
+    common::{chunked_spawn_regions, remove_unreachable_areas_returning_most_distant},
+    MapBuilder,
+};
+use crate::{
+    map::{Map, TileType},
+    Position, PsnU,
+};
+
+/// A perfect maze built with a recursive backtracker. Each maze cell is one floor tile,
+/// with a wall tile between neighbouring cells that is knocked out when they are joined.
+pub struct MazeBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl MazeBuilder {
+    pub fn new(width: usize, height: usize, new_depth: i32) -> MazeBuilder {
+        MazeBuilder {
+            map: Map::new(width, height, new_depth),
+            starting_position: Position { xx: 1, yy: 1 },
+        }
+    }
+
+    fn cell_to_pos(cell: (usize, usize)) -> Position {
+        Position {
+            xx: (2 * cell.0 + 1) as PsnU,
+            yy: (2 * cell.1 + 1) as PsnU,
+        }
+    }
+
+    fn carve(&mut self, pos: Position) {
+        let ix = self.map.pos_idx(pos);
+        self.map.tiles[ix] = TileType::Floor;
+    }
+}
+
+impl MapBuilder for MazeBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        let cells_wide = (self.map.width() - 1) / 2;
+        let cells_high = (self.map.height() - 1) / 2;
+        let cell_ix = |cell: (usize, usize)| cell.1 * cells_wide + cell.0;
+
+        let mut visited = vec![false; cells_wide * cells_high];
+        let mut stack = vec![(0_usize, 0_usize)];
+        visited[0] = true;
+        self.carve(Self::cell_to_pos((0, 0)));
+
+        while let Some(&current) = stack.last() {
+            let (cx, cy) = current;
+            let unvisited: Vec<(usize, usize)> = [
+                (cx > 0).then(|| (cx - 1, cy)),
+                (cx + 1 < cells_wide).then_some((cx + 1, cy)),
+                (cy > 0).then(|| (cx, cy - 1)),
+                (cy + 1 < cells_high).then_some((cx, cy + 1)),
+            ]
+            .into_iter()
+            .flatten()
+            .filter(|cell| !visited[cell_ix(*cell)])
+            .collect();
+
+            match rng.random_slice_entry(&unvisited) {
+                None => {
+                    stack.pop();
+                }
+                Some(&next) => {
+                    let from = Self::cell_to_pos(current);
+                    let to = Self::cell_to_pos(next);
+                    self.carve(Position {
+                        xx: (from.xx + to.xx) / 2,
+                        yy: (from.yy + to.yy) / 2,
+                    });
+                    self.carve(to);
+                    visited[cell_ix(next)] = true;
+                    stack.push(next);
+                }
+            }
+        }
+
+        let stairs_ix = remove_unreachable_areas_returning_most_distant(
+            &mut self.map,
+            self.starting_position,
+        );
+        self.map.tiles[stairs_ix] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        chunked_spawn_regions(&self.map, self.starting_position)
+    }
+}
@@ -0,0 +1,183 @@
+use bracket_lib::random::RandomNumberGenerator;
+use specs::prelude::*;
+
+use crate::{display_state::DisplayState, map::Map, random_table::RandomTable, Position};
+
+mod bsp_dungeon;
+mod cellular_automata;
+mod common;
+mod drunkard;
+mod maze;
+mod simple_map;
+
+use bsp_dungeon::BspDungeonBuilder;
+use cellular_automata::CellularAutomataBuilder;
+use drunkard::DrunkardsWalkBuilder;
+use maze::MazeBuilder;
+use simple_map::SimpleMapBuilder;
+
+/// A dungeon generation algorithm for a single level. Callers create a builder
+/// for the new depth, call `build_map` once, and then query the results.
+pub trait MapBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator);
+    fn get_map(&self) -> Map;
+    fn get_starting_position(&self) -> Position;
+    /// Groups of map indices that are each populated like a room would be.
+    /// The region containing the starting position is left empty.
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapBuilderKind {
+    RoomsAndCorridors,
+    Bsp,
+    CellularAutomata,
+    DrunkardsWalk,
+    Maze,
+}
+
+fn builder_table<'a>(new_depth: i32) -> RandomTable<'a, MapBuilderKind> {
+    let depth_u16 = <u32 as TryInto<u16>>::try_into(new_depth.unsigned_abs().min(20)).unwrap();
+    RandomTable::<'a, MapBuilderKind>::new(MapBuilderKind::RoomsAndCorridors, 30)
+        .add(MapBuilderKind::Bsp, 25)
+        .add(MapBuilderKind::CellularAutomata, 10 + depth_u16)
+        .add(MapBuilderKind::DrunkardsWalk, 10 + depth_u16)
+        .add(MapBuilderKind::Maze, 2 * depth_u16)
+}
+
+pub fn new_builder(
+    kind: MapBuilderKind,
+    width: usize,
+    height: usize,
+    new_depth: i32,
+) -> Box<dyn MapBuilder> {
+    match kind {
+        MapBuilderKind::RoomsAndCorridors => {
+            Box::new(SimpleMapBuilder::new(width, height, new_depth))
+        }
+        MapBuilderKind::Bsp => Box::new(BspDungeonBuilder::new(width, height, new_depth)),
+        MapBuilderKind::CellularAutomata => {
+            Box::new(CellularAutomataBuilder::new(width, height, new_depth))
+        }
+        MapBuilderKind::DrunkardsWalk => {
+            Box::new(DrunkardsWalkBuilder::new(width, height, new_depth))
+        }
+        MapBuilderKind::Maze => Box::new(MazeBuilder::new(width, height, new_depth)),
+    }
+}
+
+pub fn random_builder(
+    rng: &mut RandomNumberGenerator,
+    width: usize,
+    height: usize,
+    new_depth: i32,
+) -> Box<dyn MapBuilder> {
+    let kind = builder_table(new_depth).roll(rng);
+    new_builder(kind, width, height, new_depth)
+}
+
+/// The map covers the whole display, less the UI panel at the bottom.
+pub fn map_dimensions(display: &DisplayState) -> (usize, usize) {
+    let map_width: usize = display.width.try_into().unwrap();
+    let display_height: usize = display.height.try_into().unwrap();
+    (map_width, display_height - crate::gui::PANEL_HEIGHT)
+}
+
+/// Picks a builder for `new_depth` from the builder table and runs it.
+pub fn build_random_map(
+    ecs: &World,
+    display: &DisplayState,
+    new_depth: i32,
+) -> Box<dyn MapBuilder> {
+    let (width, height) = map_dimensions(display);
+    let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+    let mut builder = random_builder(&mut rng, width, height, new_depth);
+    builder.build_map(&mut rng);
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use bracket_lib::prelude::DijkstraMap;
+
+    use super::*;
+    use crate::map::TileType;
+
+    /// Checks the player starts on floor and can walk from there to the stairs.
+    fn check_stairs(builder: &dyn MapBuilder, what: &str) {
+        let mut map = builder.get_map();
+        map.populate_blocked();
+        let start_ix = map.pos_idx(builder.get_starting_position());
+        assert_eq!(map.tiles[start_ix], TileType::Floor, "{}", what);
+
+        let stairs_ix = map
+            .tiles
+            .iter()
+            .position(|tile| *tile == TileType::DownStairs)
+            .unwrap_or_else(|| panic!("{} has no stairs", what));
+        let dijkstra_map = DijkstraMap::new(map.width(), map.height(), &[start_ix], &map, 1000.0);
+        assert!(
+            dijkstra_map.map[stairs_ix] < f32::MAX,
+            "{} has unreachable stairs",
+            what
+        );
+    }
+
+    /// Builds a few seeded levels of `kind` and checks each is playable: the player starts
+    /// on floor, can walk to the stairs, and has somewhere for monsters to be.
+    fn check_builder(kind: MapBuilderKind) {
+        let (width, height) = map_dimensions(&DisplayState::default());
+        (1..=5).for_each(|seed| {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            let mut builder = new_builder(kind, width, height, 1);
+            builder.build_map(&mut rng);
+            let what = format!("{:?} seed {}", kind, seed);
+            check_stairs(&*builder, &what);
+            assert!(
+                !builder.get_spawn_regions().is_empty(),
+                "{} has nowhere to spawn",
+                what
+            );
+        });
+    }
+
+    /// A map with room for just the one room, which the player starts in.
+    fn check_lone_room(kind: MapBuilderKind, size: usize) {
+        let mut builder = new_builder(kind, size, size, 1);
+        builder.build_map(&mut RandomNumberGenerator::seeded(1));
+        assert_eq!(builder.get_map().rooms.len(), 1);
+        check_stairs(&*builder, &format!("{:?} lone room", kind));
+    }
+
+    #[test]
+    fn rooms_and_corridors_are_playable() {
+        check_builder(MapBuilderKind::RoomsAndCorridors);
+    }
+
+    #[test]
+    fn bsp_dungeons_are_playable() {
+        check_builder(MapBuilderKind::Bsp);
+    }
+
+    #[test]
+    fn lone_rooms_have_stairs_away_from_the_start() {
+        check_lone_room(MapBuilderKind::RoomsAndCorridors, 12);
+        // Too small for any room to fit, so it falls back on one in the middle
+        check_lone_room(MapBuilderKind::Bsp, 10);
+    }
+
+    #[test]
+    fn caves_are_playable() {
+        check_builder(MapBuilderKind::CellularAutomata);
+    }
+
+    #[test]
+    fn drunkards_walks_are_playable() {
+        check_builder(MapBuilderKind::DrunkardsWalk);
+    }
+
+    #[test]
+    fn mazes_are_playable() {
+        check_builder(MapBuilderKind::Maze);
+    }
+}
@@ -0,0 +1,80 @@
+use bracket_lib::random::RandomNumberGenerator;
+
+use super::{
+    common::{room_spawn_regions, room_stairs_ix},
+    MapBuilder,
+};
+use crate::{
+    map::{Map, TileType},
+    rect::Rect,
+    Position, PsnU,
+};
+
+/// The original generator: randomly placed rooms joined by L-shaped corridors.
+pub struct SimpleMapBuilder {
+    map: Map,
+}
+
+impl SimpleMapBuilder {
+    pub fn new(width: usize, height: usize, new_depth: i32) -> SimpleMapBuilder {
+        SimpleMapBuilder {
+            map: Map::new(width, height, new_depth),
+        }
+    }
+}
+
+impl MapBuilder for SimpleMapBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        const MAX_ROOMS: u16 = 30;
+        const MIN_SIZE: PsnU = 6;
+        const MAX_SIZE: PsnU = 10;
+
+        let map = &mut self.map;
+        let map_width = map.width_psnu;
+        let map_height = map.height_psnu;
+
+        (0..MAX_ROOMS).for_each(|_| {
+            let ww = rng.range(MIN_SIZE, MAX_SIZE);
+            let hh = rng.range(MIN_SIZE, MAX_SIZE);
+            let xx = rng.range(1, map_width - ww - 1);
+            let yy = rng.range(1, map_height - hh - 1);
+
+            let new_room = Rect::new(xx, yy, ww, hh);
+
+            let room_ok = map
+                .rooms
+                .iter()
+                .all(|other_room| !new_room.intersect(other_room));
+            if room_ok {
+                map.add_room(&new_room);
+                if let Some(prev_room) = map.rooms.last() {
+                    let new_center = new_room.center();
+                    let pre_center = prev_room.center();
+                    if rng.range(0, 2) == 1 {
+                        map.add_horizontal_tunnel(pre_center.xx, new_center.xx, pre_center.yy);
+                        map.add_vertical_tunnel(pre_center.yy, new_center.yy, new_center.xx);
+                    } else {
+                        map.add_vertical_tunnel(pre_center.yy, new_center.yy, pre_center.xx);
+                        map.add_horizontal_tunnel(pre_center.xx, new_center.xx, new_center.yy);
+                    }
+                }
+                map.rooms.push(new_room)
+            }
+        });
+
+        let stairs_ix = room_stairs_ix(map);
+        map.tiles[stairs_ix] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.map.rooms[0].center()
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        room_spawn_regions(&self.map)
+    }
+}
@@ -1,9 +1,13 @@
 use crate::{
-    components::{debug_name, Equipped, Item},
+    combat::{resolve_attack, Attack, AttackOutcome, UNARMED_DICE},
+    components::{debug_name, Creature, Equipped, Item, LastHitBy},
+    equipment::{get_equipped_items, EquipSlot, EquipmentType, MaterialEffect, WeaponType},
     gamelog::GameLog,
+    util::fmt_list,
 };
 
 use super::{CombatStats, EventIncomingDamage, EventWantsToMelee, Name};
+use bracket_lib::random::RandomNumberGenerator;
 use specs::prelude::*;
 
 pub struct MeleeCombatSystem {}
@@ -13,11 +17,14 @@ impl<'a> System<'a> for MeleeCombatSystem {
         Entities<'a>,
         WriteExpect<'a, GameLog>,
         ReadStorage<'a, Name>,
-        ReadStorage<'a, CombatStats>,
+        WriteStorage<'a, CombatStats>,
         WriteStorage<'a, EventIncomingDamage>,
         WriteStorage<'a, EventWantsToMelee>,
         ReadStorage<'a, Equipped>,
         ReadStorage<'a, Item>,
+        WriteStorage<'a, LastHitBy>,
+        ReadStorage<'a, Creature>,
+        WriteExpect<'a, RandomNumberGenerator>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -25,13 +32,17 @@ impl<'a> System<'a> for MeleeCombatSystem {
             entities,
             mut log,
             names,
-            combat_stats,
+            mut combat_stats,
             mut inflict_damage,
             mut wants_melee,
             equipped,
             items,
+            mut last_hit_by,
+            creatures,
+            mut rng,
         ) = data;
 
+        let mut lifesteals: Vec<(Entity, u16)> = vec![];
         (&entities, &names, &combat_stats, &mut wants_melee)
             .join()
             .for_each(|(entity, name, stats, wants_melee)| {
@@ -39,12 +50,48 @@ impl<'a> System<'a> for MeleeCombatSystem {
                 let debug_name = debug_name();
                 let target_name = names.get(target).unwrap_or(&debug_name);
                 let target_stats_opt = combat_stats.get(target);
+                // Each melee weapon's name, power bonus and material effect on the target
+                let weapon_effects: Vec<(String, i16, MaterialEffect)> =
+                    creatures.get(target).map_or_else(Vec::new, |creature| {
+                        (&entities, &items, &equipped)
+                            .join()
+                            .filter(|(_e, _itm, eq)| eq.owner == entity)
+                            .filter_map(|(e, item, _eq)| {
+                                item.equip_opt()
+                                    .filter(|et| {
+                                        matches!(
+                                            et.equipment_type,
+                                            EquipmentType::Weapon(WeaponType::Melee(_))
+                                        )
+                                    })
+                                    .map(|et| {
+                                        (
+                                            names.get(e).map_or_else(
+                                                || debug_name().name,
+                                                |n| n.name.clone(),
+                                            ),
+                                            et.power_bonus(),
+                                            et.material.against(*creature),
+                                        )
+                                    })
+                            })
+                            .collect()
+                    });
+                let material_bonus_opt = material_bonus(
+                    &weapon_effects
+                        .iter()
+                        .map(|(_, power, effect)| (*power, *effect))
+                        .collect::<Vec<_>>(),
+                );
                 let offensive_bonus = if stats.hp > 0 {
                     (&entities, &items, &equipped)
                         .join()
                         .filter(|(_e, _itm, eq)| eq.owner == entity)
-                        .filter_map(|(_e, item, _eq)| item.equip_opt().map(|et| et.power_bonus()))
+                        // Bows and crossbows make poor clubs
+                        .filter_map(|(_e, item, _eq)| item.equip_opt().filter(|et| !et.is_ranged()))
+                        .map(|et| et.power_bonus())
                         .sum::<i16>()
+                        + material_bonus_opt.unwrap_or(0)
                 } else {
                     0
                 };
@@ -83,21 +130,137 @@ impl<'a> System<'a> for MeleeCombatSystem {
                             .defense
                             .saturating_sub(defensive_bonus.abs().try_into().unwrap())
                     };
-                    let damage = stats_power_with_bonus.saturating_sub(defense_with_bonus);
-                    if damage > 0 && target_stats.hp > 0 {
-                        EventIncomingDamage::new_damage(&mut inflict_damage, target, damage);
-                        log.entries.push(format!(
-                            "{} hits {} for {} hp.",
-                            name.name, target_name.name, damage
-                        ));
-                    } else {
+                    // The main hand weapon does the damage, else whatever is in the off hand
+                    let equipment = get_equipped_items(&entities, &items, &equipped, entity);
+                    let weapon_opt = [EquipSlot::MainHand, EquipSlot::OffHand]
+                        .iter()
+                        .filter_map(|slot| equipment.get(slot))
+                        .find(|(eq, _)| eq.damage_dice().is_some())
+                        .map(|(eq, _)| eq);
+                    let attack = Attack {
+                        attack_bonus: stats_power_with_bonus as i16
+                            + weapon_opt.map_or(0, |eq| eq.accuracy()),
+                        defense: defense_with_bonus as i16,
+                        dice: weapon_opt
+                            .and_then(|eq| eq.damage_dice())
+                            .unwrap_or(UNARMED_DICE),
+                        damage_bonus: (stats_power_with_bonus as i16 - defense_with_bonus as i16)
+                            / 2,
+                    };
+                    if material_bonus_opt.is_none() && target_stats.hp > 0 {
                         log.entries.push(format!(
-                            "{} is unable to hurt {}.",
-                            name.name, target_name.name
+                            "{}'s {} is useless against {}.",
+                            name.name,
+                            fmt_list(
+                                &weapon_effects
+                                    .iter()
+                                    .map(|(weapon, _, _)| weapon.clone())
+                                    .collect::<Vec<_>>()
+                            ),
+                            target_name.name
                         ));
+                    } else if target_stats.hp > 0 {
+                        let result = resolve_attack(&attack, &mut |sides| rng.range(1, sides + 1));
+                        let details = if log.verbose {
+                            format!(" ({})", result.details(&attack))
+                        } else {
+                            String::new()
+                        };
+                        let damage = result.damage;
+                        match result.outcome {
+                            AttackOutcome::Fumble => log.entries.push(format!(
+                                "{} fumbles the attack on {}.{}",
+                                name.name, target_name.name, details
+                            )),
+                            AttackOutcome::Miss => log.entries.push(format!(
+                                "{} misses {}.{}",
+                                name.name, target_name.name, details
+                            )),
+                            AttackOutcome::Hit => log.entries.push(format!(
+                                "{} hits {} for {} hp.{}",
+                                name.name, target_name.name, damage, details
+                            )),
+                            AttackOutcome::Critical => log.entries.push(format!(
+                                "{} critically hits {} for {} hp!{}",
+                                name.name, target_name.name, damage, details
+                            )),
+                        }
+                        if damage > 0 {
+                            EventIncomingDamage::new_damage(&mut inflict_damage, target, damage);
+                            LastHitBy::record(&mut last_hit_by, target, entity);
+                            weapon_effects
+                                .iter()
+                                .filter(|(_, _, effect)| {
+                                    matches!(effect, MaterialEffect::Modified(m) if *m > 0)
+                                })
+                                .for_each(|(weapon, _, _)| {
+                                    log.entries.push(format!(
+                                        "The {} is especially effective against {}!",
+                                        weapon, target_name.name
+                                    ))
+                                });
+                            let lifesteal_percent = (&items, &equipped)
+                                .join()
+                                .filter(|(_itm, eq)| eq.owner == entity)
+                                .filter_map(|(item, _eq)| item.equip_opt())
+                                .map(|et| et.lifesteal_percent())
+                                .sum::<u16>();
+                            if lifesteal_percent > 0 {
+                                let drained = (damage * lifesteal_percent / 100).max(1);
+                                lifesteals.push((entity, drained));
+                                log.entries
+                                    .push(format!("{} drains {} hp.", name.name, drained));
+                            }
+                        }
                     }
                 }
             });
         wants_melee.clear();
+
+        lifesteals.into_iter().for_each(|(entity, drained)| {
+            if let Some(stats) = combat_stats.get_mut(entity) {
+                stats.hp = stats.max_hp.min(stats.hp + drained);
+            }
+        });
+    }
+}
+
+/// The extra power from weapon materials, given each melee weapon's power bonus and
+/// material effect. A useless weapon loses its power bonus; `None` if every weapon is useless.
+fn material_bonus(weapon_effects: &[(i16, MaterialEffect)]) -> Option<i16> {
+    let all_useless = !weapon_effects.is_empty()
+        && weapon_effects
+            .iter()
+            .all(|(_, effect)| *effect == MaterialEffect::Useless);
+    (!all_useless).then(|| {
+        weapon_effects
+            .iter()
+            .map(|(power_bonus, effect)| match effect {
+                MaterialEffect::Normal => 0,
+                MaterialEffect::Modified(modifier) => *modifier,
+                MaterialEffect::Useless => -power_bonus,
+            })
+            .sum()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn useless_weapons_lose_their_bonus() {
+        assert_eq!(material_bonus(&[]), Some(0));
+        assert_eq!(
+            material_bonus(&[
+                (3, MaterialEffect::Useless),
+                (2, MaterialEffect::Modified(4))
+            ]),
+            Some(1)
+        );
+        assert_eq!(
+            material_bonus(&[(3, MaterialEffect::Useless), (1, MaterialEffect::Useless)]),
+            None
+        );
     }
 }
@@ -1,105 +1,507 @@
+use std::{cmp::Ordering, fmt::Display};
+
 use crate::{
-    components::*, gamelog::GameLog, map::Map, player::get_player_entities_with_pos, RunState,
+    ai_pathing::AiPathing,
+    components::*,
+    equipment::get_equipped_items,
+    faction::{reaction, Reaction},
+    gamelog::GameLog,
+    map::Map,
+    ranged_combat_system::find_loadout,
+    status_effect_system::has_status,
 };
 
 use super::{Monster, Viewshed};
-use bracket_lib::terminal::DistanceAlg;
+use bracket_lib::{
+    random::RandomNumberGenerator,
+    terminal::{DistanceAlg, Point},
+};
+use itertools::Itertools;
 use specs::prelude::*;
 
+/// One in this many idle turns, a monster sets off to wander.
+const WANDER_CHANCE: i32 = 10;
+/// How long a monster hunts for a foe it has lost sight of before giving up.
+const SEARCH_TURNS: u16 = 10;
+/// HP a fleeing monster recovers each turn it spends out of its foes' sight.
+const REST_HEALING: u16 = 1;
+/// One in this many turns with a foe in range, a caster casts; otherwise it
+/// gathers itself.
+const CAST_CHANCE: i32 = 2;
+/// A monster drinks a healing potion once its HP drops below this percentage.
+const DRINK_BELOW_PERCENT: u32 = 50;
+
+impl Display for AiState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiState::Idle => write!(f, "idle"),
+            AiState::Wandering(_) => write!(f, "wander"),
+            AiState::Chasing(_, turns_unseen) => write!(f, "chase {}", turns_unseen),
+            AiState::Fleeing => write!(f, "flee"),
+        }
+    }
+}
+
+/// Takes a step along the shortest path to `destination`, somewhere nobody else is bound
+/// for, so not worth a shared map; false if there is no path.
+fn step_towards(map: &mut Map, pos: &mut Position, destination: &Position) -> bool {
+    // Someone may be standing at the destination; they are who is being headed for
+    let destination_ix = destination.idx(map.width_psnu);
+    let destination_blocked = map.blocked[destination_ix];
+    map.blocked[destination_ix] = false;
+    let path = bracket_lib::prelude::a_star_search(pos.idx(map.width_psnu), destination_ix, &*map);
+    map.blocked[destination_ix] = destination_blocked;
+    if path.success && path.steps.len() > 1 && !map.blocked[path.steps[1]] {
+        let new_pos = map.idx_to_pos(path.steps[1]);
+        map.move_blocker(pos, &new_pos);
+        true
+    } else {
+        false
+    }
+}
+
+/// Uses `item` on itself: drinks a potion or puts on a piece of gear.
+fn use_on_self(wants_to_use: &mut WriteStorage<EventWantsToUseItem>, user: Entity, item: Entity) {
+    wants_to_use
+        .insert(user, EventWantsToUseItem { item, target: None })
+        .unwrap_or_else(|er| panic!("Unable to insert item use: {}", er));
+}
+
+/// Moves to `next_ix`, if there is anywhere to go; true if it moved.
+fn step_to(map: &mut Map, pos: &mut Position, next_ix: Option<usize>) -> bool {
+    if let Some(ix) = next_ix {
+        let new_pos = map.idx_to_pos(ix);
+        map.move_blocker(pos, &new_pos);
+        true
+    } else {
+        false
+    }
+}
+
+fn distance(pos: Position, other: Position) -> f32 {
+    DistanceAlg::Pythagoras.distance2d(Point::from(pos), Point::from(other))
+}
+
+fn is_adjacent(pos: Position, other: Position) -> bool {
+    distance(pos, other) < 1.5
+}
+
+/// Whichever of `others` is closest to `pos`.
+fn nearest<T: Copy>(
+    pos: Position,
+    others: impl Iterator<Item = (T, Position)>,
+) -> Option<(T, Position)> {
+    others.min_by(|(_, pos1), (_, pos2)| {
+        distance(pos, *pos1)
+            .partial_cmp(&distance(pos, *pos2))
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Takes a step in a random direction, if that way isn't blocked.
+fn stumble(map: &mut Map, pos: &mut Position, rng: &mut RandomNumberGenerator) -> bool {
+    let try_pos = map.dest_from_delta(pos, rng.range(-1, 2), rng.range(-1, 2));
+    if try_pos != *pos && !map.blocked[map.pos_idx(try_pos)] {
+        map.move_blocker(pos, &try_pos);
+        true
+    } else {
+        false
+    }
+}
+
+/// Keeps `actors` in step with a monster that has just moved.
+fn moved_to(actors: &mut [(Entity, Position)], mover: Entity, pos: Position) {
+    if let Some(actor) = actors.iter_mut().find(|(actor, _)| *actor == mover) {
+        actor.1 = pos;
+    }
+}
+
+/// Somewhere to wander to: the middle of a random room, or any open floor on levels
+/// without rooms.
+fn wander_destination(map: &Map, rng: &mut RandomNumberGenerator) -> Option<Position> {
+    if map.rooms.is_empty() {
+        let open_tiles: Vec<usize> = map.blocked.iter().positions(|blocked| !blocked).collect();
+        rng.random_slice_entry(&open_tiles)
+            .map(|ix| map.idx_to_pos(*ix))
+    } else {
+        rng.random_slice_entry(&map.rooms).map(|room| room.center())
+    }
+}
+
 pub struct MonsterAI {}
 
 impl<'a> System<'a> for MonsterAI {
     type SystemData = (
         Entities<'a>,
         WriteExpect<'a, GameLog>,
-        ReadExpect<'a, RunState>,
         WriteExpect<'a, Map>,
-        ReadStorage<'a, Player>,
         WriteStorage<'a, Viewshed>,
         ReadStorage<'a, Monster>,
         ReadStorage<'a, Name>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, EventWantsToMelee>,
-        WriteStorage<'a, Confusion>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, MyTurn>,
+        WriteStorage<'a, AiState>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, Morale>,
+        (
+            ReadStorage<'a, Item>,
+            ReadStorage<'a, Equipped>,
+            ReadStorage<'a, Ammunition>,
+            ReadStorage<'a, InBackpack>,
+        ),
+        ReadStorage<'a, Spell>,
+        ReadStorage<'a, Range>,
+        WriteStorage<'a, EventWantsToShoot>,
+        WriteStorage<'a, EventWantsToUseItem>,
+        ReadStorage<'a, ProvidesHealing>,
+        WriteStorage<'a, EventWantsToPickupItem>,
+        ReadStorage<'a, Faction>,
+        ReadStorage<'a, LastHitBy>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteExpect<'a, AiPathing>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
             entities,
             mut log,
-            runstate,
             mut map,
-            players,
             mut viewsheds,
             monsters,
             names,
             mut positions,
             mut wants_to_melee,
-            mut confused,
+            statuses,
+            my_turns,
+            mut ai_states,
+            mut combat_stats,
+            mut morales,
+            (items, equipped, ammunition, backpack),
+            spells,
+            ranges,
+            mut wants_to_shoot,
+            mut wants_to_use,
+            healing,
+            mut wants_to_pickup,
+            factions,
+            last_hit_by,
+            mut rng,
+            mut pathing,
         ) = data;
 
-        if *runstate == RunState::MonsterTurn {
-            let player_entities_with_pos =
-                get_player_entities_with_pos(&entities, &players, &positions);
+        pathing.new_turn();
+
+        // Everyone who might be fought or fled from, kept up to date as monsters move
+        let mut actors: Vec<(Entity, Position)> = (&entities, &positions, &combat_stats, &factions)
+            .join()
+            .map(|(actor, actor_pos, _, _)| (actor, *actor_pos))
+            .collect();
+        // Items lying about; each can only be claimed by one monster a turn
+        let mut loose_items: Vec<(Entity, Position)> = (&entities, &items, &positions)
+            .join()
+            .map(|(item, _, item_pos)| (item, *item_pos))
+            .collect();
+
+        (
+            &entities,
+            &mut viewsheds,
+            &monsters,
+            &names,
+            &mut positions,
+            &my_turns,
+            &mut ai_states,
+        )
+            .join()
+            .for_each(|(entity, viewshed, _monster, name, pos, _turn, ai_state)| {
+                if has_status(&statuses, entity, StatusKind::Confusion) {
+                    // Confused monsters stumble about at random
+                    if stumble(&mut map, pos, &mut rng) {
+                        viewshed.dirty = true;
+                        moved_to(&mut actors, entity, *pos);
+                    }
+                    return;
+                }
+
+                // A grudge against whoever last hurt it outweighs any faction's reaction
+                let feels = |other: Entity| {
+                    if last_hit_by
+                        .get(entity)
+                        .map_or(false, |hit| hit.attacker == other)
+                    {
+                        Reaction::Hostile
+                    } else {
+                        reaction(&factions, entity, other)
+                    }
+                };
+                let seen: Vec<(Reaction, Position)> = actors
+                    .iter()
+                    .filter(|(other, other_pos)| {
+                        *other != entity && viewshed.visible_tiles.contains(&(*other_pos).into())
+                    })
+                    .map(|(other, other_pos)| (feels(*other), *other_pos))
+                    .collect();
+                let ixs_of = |feelings: &[Reaction]| -> Vec<usize> {
+                    seen.iter()
+                        .filter(|(feeling, _)| feelings.contains(feeling))
+                        .map(|(_, other_pos)| map.pos_idx(*other_pos))
+                        .collect()
+                };
+                let feared_ixs = ixs_of(&[Reaction::Fearful]);
+                let threat_ixs = ixs_of(&[Reaction::Hostile, Reaction::Fearful]);
+                let target = nearest(
+                    *pos,
+                    actors.iter().copied().filter(|(other, other_pos)| {
+                        *other != entity
+                            && viewshed.visible_tiles.contains(&(*other_pos).into())
+                            && feels(*other) == Reaction::Hostile
+                    }),
+                );
+                let in_view = map.visible_tiles[map.pos_idx(*pos)];
+                if let (Some(stats), Some(morale)) = (combat_stats.get(entity), morales.get(entity))
+                {
+                    if *ai_state != AiState::Fleeing && morale.breaks(stats) {
+                        *ai_state = AiState::Fleeing;
+                        if in_view {
+                            log.entries.push(format!("{} turns to flee!", name.name));
+                        }
+                    } else if *ai_state == AiState::Fleeing && morale.recovered(stats) {
+                        *ai_state = AiState::Idle;
+                        if in_view {
+                            log.entries.push(format!("{} regroups.", name.name));
+                        }
+                    }
+                }
 
-            (&entities, &mut viewsheds, &monsters, &names, &mut positions)
-                .join()
-                .for_each(|(entity, viewshed, _monster, name, pos)| {
-                    let (is_confused, confusion_opt) = match confused.get_mut(entity) {
-                        conf @ Some(_) => (true, conf),
-                        None => (false, None),
+                let pack: Vec<Entity> = (&entities, &backpack)
+                    .join()
+                    .filter(|(_, pack)| pack.owner == entity)
+                    .map(|(item, _)| item)
+                    .collect();
+                let is_hurt = combat_stats.get(entity).map_or(false, |stats| {
+                    u32::from(stats.hp) * 100 < u32::from(stats.max_hp) * DRINK_BELOW_PERCENT
+                });
+                if let Some(potion) = pack
+                    .iter()
+                    .copied()
+                    .find(|item| healing.get(*item).is_some())
+                    .filter(|_| is_hurt)
+                {
+                    use_on_self(&mut wants_to_use, entity, potion);
+                    return;
+                }
+                if threat_ixs.is_empty()
+                    && matches!(*ai_state, AiState::Idle | AiState::Wandering(_))
+                {
+                    // With nobody to fight, it sees to its gear
+                    let worn = get_equipped_items(&entities, &items, &equipped, entity);
+                    let is_upgrade = |item: Entity| {
+                        items
+                            .get(item)
+                            .and_then(Item::equip_opt)
+                            .map_or(false, |equipment| {
+                                !equipment.is_ranged() && equipment.is_upgrade_over(&worn)
+                            })
                     };
+                    if let Some(upgrade) = pack.iter().copied().find(|item| is_upgrade(*item)) {
+                        use_on_self(&mut wants_to_use, entity, upgrade);
+                        return;
+                    }
+                    let nearest_loot = nearest(
+                        *pos,
+                        loose_items.iter().copied().filter(|(item, item_pos)| {
+                            viewshed.visible_tiles.contains(&(*item_pos).into())
+                                && (is_upgrade(*item) || healing.get(*item).is_some())
+                        }),
+                    );
+                    if let Some((item, item_pos)) = nearest_loot {
+                        if item_pos == *pos {
+                            wants_to_pickup
+                                .insert(
+                                    entity,
+                                    EventWantsToPickupItem {
+                                        collected_by: entity,
+                                        item,
+                                    },
+                                )
+                                .unwrap_or_else(|er| {
+                                    panic!("Unable to insert pickup for {}: {}", name.name, er)
+                                });
+                            loose_items.retain(|(loose, _)| *loose != item);
+                            return;
+                        }
+                        if step_towards(&mut map, pos, &item_pos) {
+                            viewshed.dirty = true;
+                            moved_to(&mut actors, entity, *pos);
+                            return;
+                        }
+                    }
+                }
 
-                    let can_act = !is_confused;
+                let mut attack = |target: Entity| {
+                    wants_to_melee
+                        .insert(entity, EventWantsToMelee { target })
+                        .unwrap_or_else(|er| {
+                            panic!("Unable to insert attack from {}: {}", name.name, er)
+                        });
+                };
 
-                    if can_act {
-                        player_entities_with_pos
+                let moved = match (*ai_state, target) {
+                    // Broken monsters run from every foe in sight, the rest only from what they fear
+                    (_, _)
+                        if (*ai_state == AiState::Fleeing && !threat_ixs.is_empty())
+                            || !feared_ixs.is_empty() =>
+                    {
+                        let flee_from = if *ai_state == AiState::Fleeing {
+                            &threat_ixs
+                        } else {
+                            &feared_ixs
+                        };
+                        let next_ix = pathing.flee_step(&*map, map.pos_idx(*pos), flee_from);
+                        let fled = step_to(&mut map, pos, next_ix);
+                        // Cornered, it fights
+                        if let Some((target_entity, _)) =
+                            target.filter(|(_, target_pos)| !fled && is_adjacent(*pos, *target_pos))
+                        {
+                            attack(target_entity);
+                        }
+                        fled
+                    }
+                    (AiState::Fleeing, _) => {
+                        // Out of sight, it gets its breath back
+                        if let Some(stats) = combat_stats.get_mut(entity) {
+                            stats.hp = stats.max_hp.min(stats.hp + REST_HEALING);
+                        }
+                        if let Some(morale) = morales.get_mut(entity) {
+                            morale.rest();
+                        }
+                        false
+                    }
+                    (_, Some((target_entity, target_pos))) => {
+                        *ai_state = AiState::Chasing(target_pos, 0);
+                        let distance = distance(*pos, target_pos);
+                        let loadout_opt = find_loadout(
+                            &entities,
+                            &items,
+                            &equipped,
+                            &ammunition,
+                            &backpack,
+                            entity,
+                        )
+                        .ok();
+                        let known_spells: Vec<(Entity, u16)> = (&entities, &spells, &ranges)
+                            .join()
+                            .filter(|(_, spell, _)| spell.caster == entity)
+                            .map(|(spell_ent, _, range)| (spell_ent, range.range.0))
+                            .collect();
+                        let spells_in_range: Vec<Entity> = known_spells
                             .iter()
-                            .for_each(|(player_entity, player_pos)| {
-                                if viewshed.visible_tiles.contains(&(*player_pos).into()) {
-                                    let distance = DistanceAlg::Pythagoras
-                                        .distance2d((*pos).into(), (*player_pos).into());
-                                    if distance < 1.5 {
-                                        wants_to_melee
-                                            .insert(
-                                                entity,
-                                                EventWantsToMelee {
-                                                    target: *player_entity,
-                                                },
+                            .filter(|(_, range)| distance <= f32::from(*range))
+                            .map(|(spell_ent, _)| *spell_ent)
+                            .collect();
+                        let keeps_distance = loadout_opt.is_some() || !known_spells.is_empty();
+                        // Those that attack from range close in only until they can
+                        let reach = known_spells
+                            .iter()
+                            .map(|(_, range)| *range)
+                            .chain(loadout_opt.as_ref().map(|loadout| loadout.range))
+                            .max()
+                            .unwrap_or(0);
+
+                        if is_adjacent(*pos, target_pos) {
+                            // Archers and casters back away rather than trade blows
+                            let backed_away = keeps_distance && {
+                                let next_ix =
+                                    pathing.flee_step(&*map, map.pos_idx(*pos), &threat_ixs);
+                                step_to(&mut map, pos, next_ix)
+                            };
+                            if !backed_away {
+                                attack(target_entity);
+                                if in_view {
+                                    log.entries.push(format!("{} shouts insults", name.name));
+                                }
+                            }
+                            backed_away
+                        } else if loadout_opt
+                            .filter(|loadout| distance <= f32::from(loadout.range))
+                            .is_some()
+                        {
+                            wants_to_shoot
+                                .insert(entity, EventWantsToShoot { target: target_pos })
+                                .unwrap_or_else(|er| {
+                                    panic!("Unable to insert shot from {}: {}", name.name, er)
+                                });
+                            false
+                        } else if !spells_in_range.is_empty() {
+                            if rng.range(0, CAST_CHANCE) == 0 {
+                                if let Some(spell) = rng.random_slice_entry(&spells_in_range) {
+                                    wants_to_use
+                                        .insert(
+                                            entity,
+                                            EventWantsToUseItem {
+                                                item: *spell,
+                                                target: Some(Point::from(target_pos)),
+                                            },
+                                        )
+                                        .unwrap_or_else(|er| {
+                                            panic!(
+                                                "Unable to insert spell from {}: {}",
+                                                name.name, er
                                             )
-                                            .unwrap_or_else(|er| {
-                                                panic!(
-                                                    "Unable to insert attack on player from {}: {}",
-                                                    name.name, er
-                                                )
-                                            });
-                                        log.entries.push(format!("{} shouts insults", name.name));
-                                    } else {
-                                        let path = bracket_lib::prelude::a_star_search(
-                                            pos.idx(map.width_psnu),
-                                            player_pos.idx(map.width_psnu),
-                                            &*map,
-                                        );
-                                        if path.success && path.steps.len() > 1 {
-                                            let new_pos = map.idx_to_pos(path.steps[1]);
-                                            map.move_blocker(pos, &new_pos);
-                                            viewshed.dirty = true;
-                                        }
-                                    }
+                                        });
                                 }
-                            })
-                    } else if let Some(confusion) = confusion_opt {
-                        if let Some(step) = confusion.step_sequence.pop() {
-                            let try_pos = map.dest_from_delta(pos, step.0 as i32, step.1 as i32);
-                            if !map.blocked[map.pos_idx(try_pos)] {
-                                map.move_blocker(pos, &try_pos);
-                                viewshed.dirty = true;
                             }
+                            false
                         } else {
-                            confused.remove(entity);
+                            let next_ix = pathing.approach_step(
+                                &*map,
+                                map.pos_idx(*pos),
+                                map.pos_idx(target_pos),
+                                reach,
+                            );
+                            step_to(&mut map, pos, next_ix)
                         }
                     }
-                });
-        }
+                    (AiState::Chasing(_, turns_unseen), None) if turns_unseen >= SEARCH_TURNS => {
+                        *ai_state = AiState::Idle;
+                        false
+                    }
+                    (AiState::Chasing(last_seen, turns_unseen), None) => {
+                        *ai_state = AiState::Chasing(last_seen, turns_unseen + 1);
+                        // Once at the spot, or unable to get there, search around it
+                        let next_ix = (*pos != last_seen).then(|| {
+                            pathing.approach_step(
+                                &*map,
+                                map.pos_idx(*pos),
+                                map.pos_idx(last_seen),
+                                0,
+                            )
+                        });
+                        step_to(&mut map, pos, next_ix.flatten())
+                            || stumble(&mut map, pos, &mut rng)
+                    }
+                    (AiState::Wandering(destination), None) => {
+                        let moved =
+                            *pos != destination && step_towards(&mut map, pos, &destination);
+                        if !moved {
+                            *ai_state = AiState::Idle;
+                        }
+                        moved
+                    }
+                    (AiState::Idle, None) => {
+                        if rng.range(0, WANDER_CHANCE) == 0 {
+                            if let Some(destination) = wander_destination(&map, &mut rng) {
+                                *ai_state = AiState::Wandering(destination);
+                            }
+                        }
+                        false
+                    }
+                };
+                if moved {
+                    viewshed.dirty = true;
+                    moved_to(&mut actors, entity, *pos);
+                }
+            });
     }
 }
@@ -0,0 +1,93 @@
+use specs::prelude::*;
+
+use crate::{
+    components::{CombatStats, Faction, Monster, Morale, Position, Viewshed},
+    faction::{reaction, Reaction},
+};
+
+/// Morale of monsters the raws give none.
+pub const DEFAULT_MORALE: i32 = 100;
+/// A monster flees once its courage drops below this...
+const FLEE_BELOW: i32 = 25;
+/// ...and regroups once it is back up to this, so no monster can have less.
+pub const REGROUP_AT: i32 = 50;
+/// Lost on seeing an ally die.
+const ALLY_DEATH_PENALTY: i32 = 20;
+/// Regained each turn a fleeing monster spends out of its foes' sight.
+const MORALE_RECOVERY: i32 = 5;
+
+impl Morale {
+    pub fn new(base: i32) -> Self {
+        Morale {
+            base,
+            current: base,
+        }
+    }
+
+    /// Morale scaled by how much health is left.
+    pub fn courage(&self, stats: &CombatStats) -> i32 {
+        self.current * stats.hp as i32 / stats.max_hp.max(1) as i32
+    }
+
+    pub fn breaks(&self, stats: &CombatStats) -> bool {
+        self.courage(stats) < FLEE_BELOW
+    }
+
+    pub fn recovered(&self, stats: &CombatStats) -> bool {
+        self.courage(stats) >= REGROUP_AT
+    }
+
+    /// A turn's respite from its foes.
+    pub fn rest(&mut self) {
+        self.current = self.base.min(self.current + MORALE_RECOVERY);
+    }
+}
+
+/// Every monster that saw `dead`, a friend of theirs, die at `pos` loses heart.
+pub fn ally_died(ecs: &World, dead: Entity, pos: Position) {
+    let entities = ecs.entities();
+    let monsters = ecs.read_storage::<Monster>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let factions = ecs.read_storage::<Faction>();
+    let positions = ecs.read_storage::<Position>();
+    let mut morales = ecs.write_storage::<Morale>();
+    // Monsters frozen on other levels have no position, and saw nothing
+    (&entities, &monsters, &viewsheds, &positions, &mut morales)
+        .join()
+        .filter(|(witness, _monster, viewshed, _pos, _morale)| {
+            viewshed.visible_tiles.contains(&pos.into())
+                && reaction(&factions, *witness, dead) == Reaction::Friendly
+        })
+        .for_each(|(_witness, _monster, _viewshed, _pos, morale)| {
+            morale.current = (morale.current - ALLY_DEATH_PENALTY).max(0)
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(hp: u16) -> CombatStats {
+        CombatStats {
+            max_hp: 20,
+            hp,
+            defense: 0,
+            power: 0,
+        }
+    }
+
+    #[test]
+    fn wounds_and_losses_break_morale() {
+        let mut morale = Morale::new(60);
+        assert!(!morale.breaks(&stats(10)));
+        assert!(morale.breaks(&stats(8)));
+
+        morale.current -= 2 * ALLY_DEATH_PENALTY;
+        assert!(morale.breaks(&stats(20)));
+        (0..8).for_each(|_| morale.rest());
+        assert_eq!(morale.current, 60);
+        // Regrouping takes more courage than it took to keep fighting
+        assert!(!morale.recovered(&stats(16)));
+        assert!(morale.recovered(&stats(17)));
+    }
+}
@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use bracket_lib::terminal::{BTerm, VirtualKeyCode};
+use bracket_lib::{
+    random::RandomNumberGenerator,
+    terminal::{BTerm, VirtualKeyCode},
+};
 use indexmap::IndexMap;
 use itertools::Itertools;
 use once_cell::sync::OnceCell;
@@ -8,12 +11,16 @@ use specs::{world::EntitiesRes, *};
 
 use crate::{
     components::{
-        CombatStats, EventWantsToMelee, EventWantsToPickupItem, IsPlayer, Item, Monster, Name,
-        Player, Position, Positionable, Viewshed,
+        Ammunition, CombatStats, Equipped, EventWantsToMelee, EventWantsToPickupItem, Faction,
+        InBackpack, IsPlayer, Item, Monster, Name, Player, Position, Positionable, StatusEffects,
+        StatusKind, Viewshed,
     },
+    faction::{reaction, Reaction},
     gamelog,
     gui::MainMenuSelection::*,
     map::{Map, TileType},
+    ranged_combat_system::find_loadout,
+    status_effect_system::has_status,
     RunState, State,
 };
 
@@ -28,47 +35,78 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, gs: &State) -> RunState {
     let mut players = gs.ecs.write_storage::<Player>();
     let mut viewsheds = gs.ecs.write_storage::<Viewshed>();
     let mut wants_to_melee = gs.ecs.write_storage::<EventWantsToMelee>();
-    if let Some((entity, _player, pos, viewshed)) =
+    let mut swapped_with = None;
+    let runstate = if let Some((entity, _player, pos, viewshed)) =
         (&entities, &mut players, &mut positions, &mut viewsheds)
             .join()
             .next()
     {
+        // The confused stagger off in a random direction
+        let is_confused = has_status(
+            &gs.ecs.read_storage::<StatusEffects>(),
+            entity,
+            StatusKind::Confusion,
+        );
+        let (delta_x, delta_y) = if is_confused {
+            let mut rng = gs.ecs.write_resource::<RandomNumberGenerator>();
+            (rng.range(-1, 2), rng.range(-1, 2))
+        } else {
+            (delta_x, delta_y)
+        };
         let try_pos = &gs.ecs.fetch::<Map>().dest_from_delta(pos, delta_x, delta_y);
         let combat_stats = gs.ecs.read_storage::<CombatStats>();
+        let factions = gs.ecs.read_storage::<Faction>();
+        let names = gs.ecs.read_storage::<Name>();
         let mut map = gs.ecs.fetch_mut::<Map>();
         let destination_ix = map.pos_idx(try_pos);
-        let combat = map.tile_content[destination_ix]
-            .iter()
-            .filter(|potential_target| potential_target.id() != entity.id())
-            .any(|potential_target| {
-                if let Some(_c_stats) = combat_stats.get(*potential_target) {
+        let occupant_opt =
+            map.tile_content[destination_ix]
+                .iter()
+                .copied()
+                .find(|potential_target| {
+                    *potential_target != entity && combat_stats.get(*potential_target).is_some()
+                });
+        match occupant_opt {
+            // Friends trade places rather than blows
+            Some(friend) if reaction(&factions, entity, friend) == Reaction::Friendly => {
+                swapped_with = Some((friend, *pos));
+                map.move_blocker(pos, try_pos);
+                viewshed.dirty = true;
+                if let Some(name) = names.get(friend) {
                     log.entries
-                        .push("I stab thee with righteous fury!".to_string());
-                    wants_to_melee
-                        .insert(
-                            entity,
-                            EventWantsToMelee {
-                                target: *potential_target,
-                            },
-                        )
-                        .expect("Add target failed");
-                    true
-                } else {
-                    false
+                        .push(format!("You swap places with the {}.", name.name));
                 }
-            });
-        if !combat && !map.blocked[destination_ix] {
-            map.move_blocker(pos, try_pos);
-            viewshed.dirty = true;
-            RunState::PlayerTurn
-        } else if combat {
-            RunState::PlayerTurn
-        } else {
-            RunState::AwaitingInput
+                RunState::PlayerTurn
+            }
+            Some(target) => {
+                log.entries
+                    .push("I stab thee with righteous fury!".to_string());
+                wants_to_melee
+                    .insert(entity, EventWantsToMelee { target })
+                    .expect("Add target failed");
+                RunState::PlayerTurn
+            }
+            None if !map.blocked[destination_ix] => {
+                map.move_blocker(pos, try_pos);
+                viewshed.dirty = true;
+                RunState::PlayerTurn
+            }
+            None if is_confused => RunState::PlayerTurn,
+            None => RunState::AwaitingInput,
         }
     } else {
         RunState::AwaitingInput
+    };
+    // The friend takes the player's old place once the player is no longer borrowed
+    if let Some((friend, old_pos)) = swapped_with {
+        if let Some(friend_pos) = positions.get_mut(friend) {
+            gs.ecs.fetch_mut::<Map>().move_blocker(friend_pos, &old_pos);
+        }
+        if let Some(friend_viewshed) = viewsheds.get_mut(friend) {
+            friend_viewshed.dirty = true;
+        }
     }
+    runstate
 }
 
 macro_attr! {
@@ -88,6 +126,9 @@ macro_attr! {
         DownRight,
         Rest,
         Grab,
+        Fire,
+        ToggleVerboseLog,
+        ToggleAiOverlay,
     }
 }
 
@@ -276,6 +317,27 @@ impl KeyBindings {
                     action: Arc::new(|gs| interact(&gs.ecs)),
                 },
             ),
+            (
+                PlayerAction::Fire,
+                ActionAndKeys {
+                    key_codes: vec![(VirtualKeyCode::F, vec![])],
+                    action: Arc::new(|gs| try_fire(&gs.ecs)),
+                },
+            ),
+            (
+                PlayerAction::ToggleVerboseLog,
+                ActionAndKeys {
+                    key_codes: vec![(VirtualKeyCode::V, vec![])],
+                    action: Arc::new(|gs| toggle_verbose_log(&gs.ecs)),
+                },
+            ),
+            (
+                PlayerAction::ToggleAiOverlay,
+                ActionAndKeys {
+                    key_codes: vec![(VirtualKeyCode::Grave, vec![])],
+                    action: Arc::new(toggle_ai_overlay),
+                },
+            ),
         ]
         .iter()
         .cloned()
@@ -426,6 +488,7 @@ fn interact(ecs: &World) -> RunState {
     match map_tiles[player_map_ix] {
         TileType::Floor => get_item(ecs),
         TileType::DownStairs => try_next_level(ecs),
+        TileType::UpStairs => try_previous_level(ecs),
         _ => get_item(ecs),
     }
 }
@@ -483,3 +546,59 @@ fn try_next_level(ecs: &World) -> RunState {
         RunState::AwaitingInput
     }
 }
+
+fn try_previous_level(ecs: &World) -> RunState {
+    let player_pos = get_player_pos_unwrap(ecs, PLAYER_NAME);
+    let map = ecs.fetch::<Map>();
+    let player_ix = map.pos_idx(player_pos);
+    let mut gamelog = ecs.write_resource::<gamelog::GameLog>();
+    if map.tiles[player_ix] == TileType::UpStairs && map.depth > 1 {
+        gamelog.entries.push("You climb the stairs.".to_string());
+        RunState::PreviousLevel
+    } else {
+        gamelog
+            .entries
+            .push("There is no way up from here.".to_string());
+        RunState::AwaitingInput
+    }
+}
+
+/// Shows or hides the dice rolls in combat messages; takes no time.
+fn toggle_verbose_log(ecs: &World) -> RunState {
+    let mut log = ecs.write_resource::<gamelog::GameLog>();
+    log.verbose = !log.verbose;
+    let msg = format!(
+        "Combat rolls are now {}.",
+        if log.verbose { "shown" } else { "hidden" }
+    );
+    log.entries.push(msg);
+    RunState::AwaitingInput
+}
+
+/// Shows or hides the debug view of monster AI states; takes no time.
+fn toggle_ai_overlay(gs: &mut State) -> RunState {
+    gs.display.ai_overlay = !gs.display.ai_overlay;
+    RunState::AwaitingInput
+}
+
+/// Picks a target for the equipped bow or crossbow, or for a throwing knife.
+fn try_fire(ecs: &World) -> RunState {
+    let player_entity = get_player_unwrap(ecs, PLAYER_NAME);
+    let loadout = find_loadout(
+        &ecs.entities(),
+        &ecs.read_storage::<Item>(),
+        &ecs.read_storage::<Equipped>(),
+        &ecs.read_storage::<Ammunition>(),
+        &ecs.read_storage::<InBackpack>(),
+        player_entity,
+    );
+    match loadout {
+        Ok(loadout) => RunState::ShowFiring {
+            range: loadout.range,
+        },
+        Err(msg) => {
+            ecs.write_resource::<gamelog::GameLog>().entries.push(msg);
+            RunState::AwaitingInput
+        }
+    }
+}
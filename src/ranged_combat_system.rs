@@ -0,0 +1,295 @@
+use std::ops::Deref;
+
+use bracket_lib::prelude::{line2d, LineAlg, Point};
+use specs::{
+    prelude::*,
+    saveload::{MarkedBuilder, SimpleMarker},
+    storage::MaskedStorage,
+};
+
+use crate::{
+    components::*,
+    equipment::{get_equipped_items, EquipmentType, RangedWeaponType, WeaponType},
+    gamelog::GameLog,
+    map::{Map, TileType},
+};
+
+/// How far throwing knives go; they need no launcher to lend them range.
+pub const THROWN_RANGE: u16 = 4;
+
+/// What an entity would shoot with right now.
+pub struct Loadout {
+    pub kind: RangedWeaponType,
+    pub range: u16,
+    /// The launcher's power bonus, material included; none when throwing
+    pub power_bonus: i16,
+    pub ammo: Entity,
+}
+
+/// Finds the shooter's equipped bow or crossbow and ammo for it, or failing a
+/// launcher, something to throw. The error explains what is missing.
+pub fn find_loadout<I, E, A>(
+    entities: &Entities,
+    items: I,
+    equipped: E,
+    ammunition: &Storage<Ammunition, A>,
+    backpack: &ReadStorage<InBackpack>,
+    shooter: Entity,
+) -> Result<Loadout, String>
+where
+    I: Join,
+    I::Type: IsItem,
+    E: Join,
+    E::Type: IsEquipped,
+    A: Deref<Target = MaskedStorage<Ammunition>>,
+{
+    let launcher_opt = get_equipped_items(entities, items, equipped, shooter)
+        .into_values()
+        .find_map(|(equipment, _)| match &equipment.equipment_type {
+            EquipmentType::Weapon(WeaponType::Ranged(kind, range)) => {
+                Some((kind.clone(), range.range.0, equipment.power_bonus()))
+            }
+            _ => None,
+        });
+    let (kind, range, power_bonus) =
+        launcher_opt
+            .clone()
+            .unwrap_or((RangedWeaponType::Thrown, THROWN_RANGE, 0));
+    (entities, ammunition, backpack)
+        .join()
+        .find(|(_, ammo, pack)| pack.owner == shooter && ammo.kind == kind && ammo.count > 0)
+        .map(|(ammo, _, _)| Loadout {
+            kind: kind.clone(),
+            range,
+            power_bonus,
+            ammo,
+        })
+        .ok_or_else(|| match launcher_opt {
+            Some(_) => format!("You have no {} to fire.", kind.ammo_name().to_lowercase()),
+            None => "You have no ranged weapon equipped, nor anything to throw.".to_string(),
+        })
+}
+
+pub struct RangedCombatSystem {}
+
+impl<'a> System<'a> for RangedCombatSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, GameLog>,
+        Read<'a, LazyUpdate>,
+        WriteStorage<'a, Name>,
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, Item>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Renderable>,
+        WriteStorage<'a, Ammunition>,
+        WriteStorage<'a, EventWantsToShoot>,
+        WriteStorage<'a, EventIncomingDamage>,
+        WriteStorage<'a, LastHitBy>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            map,
+            mut log,
+            lazy,
+            mut names,
+            combat_stats,
+            items,
+            equipped,
+            backpack,
+            positions,
+            renderables,
+            mut ammunition,
+            mut wants_shoot,
+            mut inflict_damage,
+            mut last_hit_by,
+        ) = data;
+
+        let shots: Vec<(Entity, Position)> = (&entities, &wants_shoot)
+            .join()
+            .map(|(shooter, shot)| (shooter, shot.target))
+            .collect();
+        wants_shoot.clear();
+
+        shots.into_iter().for_each(|(shooter, target)| {
+            let (loadout, shooter_pos) = match (
+                find_loadout(
+                    &entities,
+                    &items,
+                    &equipped,
+                    &ammunition,
+                    &backpack,
+                    shooter,
+                ),
+                positions.get(shooter),
+            ) {
+                (Ok(loadout), Some(pos)) => (loadout, *pos),
+                (Err(msg), _) => {
+                    log.entries.push(msg);
+                    return;
+                }
+                (_, None) => return,
+            };
+
+            // The shot flies until it meets a creature or a wall, reaches the target or
+            // runs out of range
+            let mut landing = shooter_pos;
+            let mut victim_opt = None;
+            for point in flight(shooter_pos.into(), target.into(), loadout.range) {
+                let ix = map.pos_idx(point);
+                if map.tiles[ix] == TileType::Wall {
+                    break;
+                }
+                landing = point.from();
+                victim_opt = map.tile_content[ix]
+                    .iter()
+                    .copied()
+                    .find(|ent| *ent != shooter && combat_stats.get(*ent).is_some());
+                if victim_opt.is_some() || landing == target {
+                    break;
+                }
+            }
+
+            let shooter_name = names
+                .get(shooter)
+                .map_or_else(|| debug_name().name, |name| name.name.clone());
+            let verb = match loadout.kind {
+                RangedWeaponType::Thrown => "throws",
+                _ => "shoots",
+            };
+            match victim_opt {
+                Some(victim) => {
+                    let victim_name = names
+                        .get(victim)
+                        .map_or_else(|| debug_name().name, |name| name.name.clone());
+                    let damage = shot_damage(
+                        &combat_stats,
+                        (&entities, &items, &equipped),
+                        shooter,
+                        victim,
+                        loadout.power_bonus,
+                    );
+                    if damage > 0 {
+                        EventIncomingDamage::new_damage(&mut inflict_damage, victim, damage);
+                        LastHitBy::record(&mut last_hit_by, victim, shooter);
+                        log.entries.push(format!(
+                            "{} {} {} for {} hp.",
+                            shooter_name, verb, victim_name, damage
+                        ));
+                    } else {
+                        log.entries.push(format!(
+                            "{} {} at {}, but is unable to hurt them.",
+                            shooter_name, verb, victim_name
+                        ));
+                    }
+                }
+                None => log
+                    .entries
+                    .push(format!("{} {} and misses.", shooter_name, verb)),
+            }
+
+            // One less in the quiver ...
+            let renderable_opt = renderables.get(loadout.ammo).cloned();
+            if let Some(stack) = ammunition.get_mut(loadout.ammo) {
+                stack.count -= 1;
+                if stack.count == 0 {
+                    entities
+                        .delete(loadout.ammo)
+                        .unwrap_or_else(|er| panic!("Unable to delete empty ammo: {}", er));
+                } else if let Some(name) = names.get_mut(loadout.ammo) {
+                    name.name = stack.name();
+                }
+            }
+            // ... and one more on the floor, to be picked up again
+            let floor_stack_opt = (&entities, &ammunition, &positions)
+                .join()
+                .find(|(_, ammo, pos)| ammo.kind == loadout.kind && **pos == landing)
+                .map(|(ent, _, _)| ent);
+            match floor_stack_opt {
+                Some(floor_stack) => {
+                    if let Some(stack) = ammunition.get_mut(floor_stack) {
+                        stack.count += 1;
+                        if let Some(name) = names.get_mut(floor_stack) {
+                            name.name = stack.name();
+                        }
+                    }
+                }
+                None => {
+                    let landed = Ammunition {
+                        kind: loadout.kind.clone(),
+                        count: 1,
+                    };
+                    let builder = lazy
+                        .create_entity(&entities)
+                        .with(landing)
+                        .with(Item::Ammunition)
+                        .with(Name {
+                            name: landed.name(),
+                        })
+                        .with(landed);
+                    let builder = match renderable_opt {
+                        Some(renderable) => builder.with(renderable),
+                        None => builder,
+                    };
+                    builder.marked::<SimpleMarker<SerializeMe>>().build();
+                }
+            }
+        });
+    }
+}
+
+/// The tiles a shot from `start` at `target` passes over, as far as `range` takes it.
+fn flight(start: Point, target: Point, range: u16) -> impl Iterator<Item = Point> {
+    line2d(LineAlg::Bresenham, start, target)
+        .into_iter()
+        .filter(move |point| *point != start)
+        .take(usize::from(range))
+}
+
+fn shot_damage<I, E>(
+    combat_stats: &ReadStorage<CombatStats>,
+    equipment: (&Entities, I, E),
+    shooter: Entity,
+    victim: Entity,
+    power_bonus: i16,
+) -> u16
+where
+    I: Join,
+    I::Type: IsItem,
+    E: Join,
+    E::Type: IsEquipped,
+{
+    let (entities, items, equipped) = equipment;
+    let power = combat_stats.get(shooter).map_or(0, |stats| stats.power);
+    let defense = combat_stats.get(victim).map_or(0, |stats| stats.defense);
+    let defense_bonus = (entities, items, equipped)
+        .join()
+        .map(|(_, item, eqpd)| (item.from(), eqpd.from()))
+        .filter(|(_, eqpd)| eqpd.owner == victim)
+        .filter_map(|(item, _)| item.equip_opt().map(|eq| eq.defense_bonus()))
+        .sum::<i16>();
+    let power_with_bonus = power.saturating_add_signed(power_bonus);
+    let defense_with_bonus = defense.saturating_add_signed(defense_bonus);
+    power_with_bonus.saturating_sub(defense_with_bonus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shots_fall_short_beyond_range() {
+        let start = Point::new(2, 2);
+        let far: Vec<Point> = flight(start, Point::new(12, 2), THROWN_RANGE).collect();
+        assert_eq!(far.len(), usize::from(THROWN_RANGE));
+        assert_eq!(far.last(), Some(&Point::new(6, 2)));
+
+        let near: Vec<Point> = flight(start, Point::new(4, 4), THROWN_RANGE).collect();
+        assert_eq!(near, vec![Point::new(3, 3), Point::new(4, 4)]);
+    }
+}
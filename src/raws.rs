@@ -0,0 +1,544 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::{
+    components::{Creature, RenderOrder, StatusKind},
+    equipment::*,
+    faction::{Reaction, MONSTER_FACTION, PLAYER_FACTION},
+    morale::REGROUP_AT,
+};
+
+/// Edited by hand to add content without recompiling; read once at startup.
+pub const RAWS_FILE: &str = "raws/spawns.json";
+/// Copy built into the binary, for the browser and when the file is missing.
+const EMBEDDED_RAWS: &str = include_str!("../raws/spawns.json");
+/// The table rolled for each spawn in a room.
+pub const ROOM_TABLE: &str = "room";
+
+static RAWS: OnceCell<Raws> = OnceCell::new();
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Raws {
+    pub entities: Vec<RawEntity>,
+    #[serde(default)]
+    pub spawn_tables: BTreeMap<String, Vec<RawSpawnEntry>>,
+    #[serde(default)]
+    pub material_tables: BTreeMap<String, Vec<RawMaterialEntry>>,
+    /// How each faction reacts to the others it meets
+    #[serde(default)]
+    pub factions: BTreeMap<String, BTreeMap<String, Reaction>>,
+}
+
+/// A bundle of components; any field left out is a component the entity lacks.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawEntity {
+    pub name: String,
+    pub renderable: RawRenderable,
+    pub stats: Option<RawStats>,
+    pub view_range: Option<i32>,
+    #[serde(default)]
+    pub monster: bool,
+    pub creature: Option<Creature>,
+    /// A monster's side, "monsters" by default
+    pub faction: Option<String>,
+    /// Energy a monster gains each tick; 100 is normal speed, a turn every tick
+    pub speed: Option<i32>,
+    /// A monster's nerve, 100 by default; it flees as this and its HP run low
+    pub morale: Option<i32>,
+    /// Mindless monsters never flee
+    #[serde(default)]
+    pub fearless: bool,
+    /// Equipment a monster spawns wearing or wielding
+    #[serde(default)]
+    pub equipped: Vec<String>,
+    /// Items a monster spawns with in its backpack
+    #[serde(default)]
+    pub carried: Vec<String>,
+    /// Ranged items whose effect a monster casts at will, such as "Magic Missile Scroll"
+    #[serde(default)]
+    pub spells: Vec<String>,
+    /// What a monster may drop when it dies
+    pub loot: Option<RawLoot>,
+    /// A monster that leaves a corpse where it dies
+    #[serde(default)]
+    pub corpse: bool,
+    #[serde(default)]
+    pub blocks_tile: bool,
+    #[serde(default)]
+    pub consumable: bool,
+    pub provides_healing: Option<u16>,
+    pub inflicts_damage: Option<u16>,
+    pub area_of_effect: Option<u16>,
+    pub range: Option<u16>,
+    pub inflicts_status: Option<RawStatus>,
+    pub equipment: Option<RawEquipment>,
+    pub ammunition: Option<RawAmmunition>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawRenderable {
+    pub glyph: char,
+    /// Equipment without a colour takes the colour of its material
+    pub fg: Option<[u8; 3]>,
+    #[serde(default)]
+    pub bg: [u8; 3],
+    pub order: RenderOrder,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawStats {
+    pub max_hp: u16,
+    pub defense: u16,
+    pub power: u16,
+}
+
+/// The effect lasts a random `min_turns..=max_turns` turns, rolled when the item spawns.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawStatus {
+    pub kind: StatusKind,
+    #[serde(default = "one")]
+    pub min_turns: u16,
+    pub max_turns: u16,
+    /// Damage or healing per turn, for poison and regeneration
+    #[serde(default = "one")]
+    pub magnitude: u16,
+}
+
+fn one() -> u16 {
+    1
+}
+
+/// `chance` percent of the time, rolls on the spawn table `table`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawLoot {
+    pub table: String,
+    pub chance: u8,
+}
+
+/// Spawns as a stack of `min_count..=max_count`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawAmmunition {
+    pub kind: RangedWeaponType,
+    pub min_count: u16,
+    pub max_count: u16,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawEquipment {
+    pub slots: EquipSlotAllowed,
+    pub kind: RawEquipmentKind,
+    pub material_table: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum RawEquipmentKind {
+    Melee(MeleeWeaponType),
+    Ranged {
+        weapon: RangedWeaponType,
+        range: u16,
+    },
+    Shield,
+    Armor,
+    Accessory,
+}
+
+/// `name` is an entity, or another spawn table to roll on.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawSpawnEntry {
+    pub name: String,
+    pub weight: u16,
+    /// Added to `weight` once per level of depth; may be negative
+    #[serde(default)]
+    pub depth_weight: i32,
+    pub min_depth: Option<i32>,
+    pub max_depth: Option<i32>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RawMaterialEntry {
+    pub material: Material,
+    pub weight: u16,
+    #[serde(default)]
+    pub depth_weight: i32,
+}
+
+fn weight_at_depth(weight: u16, depth_weight: i32, depth: i32) -> u16 {
+    (i32::from(weight) + depth_weight * depth).clamp(0, i32::from(u16::MAX)) as u16
+}
+
+impl RawSpawnEntry {
+    pub fn weight_at(&self, depth: i32) -> u16 {
+        let in_range = self.min_depth.map_or(true, |min| depth >= min)
+            && self.max_depth.map_or(true, |max| depth <= max);
+        if in_range {
+            weight_at_depth(self.weight, self.depth_weight, depth)
+        } else {
+            0
+        }
+    }
+}
+
+impl RawMaterialEntry {
+    pub fn weight_at(&self, depth: i32) -> u16 {
+        weight_at_depth(self.weight, self.depth_weight, depth)
+    }
+}
+
+impl RawEquipmentKind {
+    pub fn equipment_type(&self) -> EquipmentType {
+        match self {
+            RawEquipmentKind::Melee(weapon) => {
+                EquipmentType::Weapon(WeaponType::Melee(weapon.clone()))
+            }
+            RawEquipmentKind::Ranged { weapon, range } => {
+                EquipmentType::Weapon(WeaponType::Ranged(
+                    weapon.clone(),
+                    crate::components::Range {
+                        range: crate::components::AbilityRange(*range),
+                    },
+                ))
+            }
+            RawEquipmentKind::Shield => EquipmentType::Shield,
+            RawEquipmentKind::Armor => EquipmentType::Armor,
+            RawEquipmentKind::Accessory => EquipmentType::Accessory,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RawsError {
+    Parse(serde_json::Error),
+    Invalid(Vec<String>),
+}
+
+impl Display for RawsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawsError::Parse(err) => write!(f, "{}: {}", RAWS_FILE, err),
+            RawsError::Invalid(problems) => {
+                write!(
+                    f,
+                    "{} has problems:\n  {}",
+                    RAWS_FILE,
+                    problems.join("\n  ")
+                )
+            }
+        }
+    }
+}
+
+impl Raws {
+    pub fn entity(&self, name: &str) -> Option<&RawEntity> {
+        self.entities.iter().find(|entity| entity.name == name)
+    }
+
+    fn is_faction(&self, name: &str) -> bool {
+        name == PLAYER_FACTION || name == MONSTER_FACTION || self.factions.contains_key(name)
+    }
+
+    /// Every problem found, so a designer can fix them all in one go.
+    pub fn validate(&self) -> Result<(), RawsError> {
+        let mut problems: Vec<String> = vec![];
+        let mut names = BTreeSet::new();
+        self.entities.iter().for_each(|entity| {
+            if !names.insert(entity.name.as_str()) {
+                problems.push(format!("entity \"{}\" is defined twice", entity.name));
+            }
+            if self.spawn_tables.contains_key(&entity.name) {
+                problems.push(format!(
+                    "\"{}\" is both an entity and a spawn table",
+                    entity.name
+                ));
+            }
+            problems.extend(entity.problems(self));
+        });
+
+        self.factions.iter().for_each(|(faction, reactions)| {
+            reactions
+                .keys()
+                .filter(|other| !self.is_faction(other))
+                .for_each(|other| {
+                    problems.push(format!(
+                        "faction \"{}\" reacts to \"{}\", which is not a faction",
+                        faction, other
+                    ))
+                });
+        });
+
+        if !self.spawn_tables.contains_key(ROOM_TABLE) {
+            problems.push(format!("missing the \"{}\" spawn table", ROOM_TABLE));
+        }
+        self.spawn_tables.iter().for_each(|(table, entries)| {
+            entries.iter().for_each(|entry| {
+                if self.entity(&entry.name).is_none()
+                    && !self.spawn_tables.contains_key(&entry.name)
+                {
+                    problems.push(format!(
+                        "spawn table \"{}\" names \"{}\", which is not an entity or a spawn table",
+                        table, entry.name
+                    ));
+                }
+                if let (Some(min), Some(max)) = (entry.min_depth, entry.max_depth) {
+                    if min > max {
+                        problems.push(format!(
+                            "spawn table \"{}\": \"{}\" has min_depth {} above max_depth {}",
+                            table, entry.name, min, max
+                        ));
+                    }
+                }
+            });
+            if self.table_reaches(table, table, &mut BTreeSet::new()) {
+                problems.push(format!(
+                    "spawn table \"{}\" ends up rolling on itself",
+                    table
+                ));
+            }
+        });
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(RawsError::Invalid(problems))
+        }
+    }
+
+    fn table_reaches(&self, from: &str, target: &str, seen: &mut BTreeSet<String>) -> bool {
+        self.spawn_tables
+            .get(from)
+            .into_iter()
+            .flatten()
+            .filter(|entry| self.spawn_tables.contains_key(&entry.name))
+            .any(|entry| {
+                entry.name == target
+                    || (seen.insert(entry.name.clone())
+                        && self.table_reaches(&entry.name, target, seen))
+            })
+    }
+}
+
+impl RawEntity {
+    fn is_item(&self) -> bool {
+        self.consumable || self.equipment.is_some() || self.ammunition.is_some()
+    }
+
+    fn problems(&self, raws: &Raws) -> Vec<String> {
+        let mut problems = vec![];
+        match &self.equipment {
+            Some(equipment) => {
+                if !raws.material_tables.contains_key(&equipment.material_table) {
+                    problems.push(format!(
+                        "\"{}\" uses material table \"{}\", which does not exist",
+                        self.name, equipment.material_table
+                    ));
+                }
+            }
+            None if self.renderable.fg.is_none() => problems.push(format!(
+                "\"{}\" needs a renderable fg colour (only equipment can leave it out)",
+                self.name
+            )),
+            None => {}
+        }
+        if let Some(ammunition) = &self.ammunition {
+            if ammunition.min_count < 1 || ammunition.min_count > ammunition.max_count {
+                problems.push(format!(
+                    "\"{}\" needs 1 <= min_count <= max_count, not {} and {}",
+                    self.name, ammunition.min_count, ammunition.max_count
+                ));
+            }
+        }
+        if self.monster && self.stats.is_none() {
+            problems.push(format!("monster \"{}\" has no stats", self.name));
+        }
+        if self.creature.is_some() && !self.monster {
+            problems.push(format!(
+                "\"{}\" has a creature kind but is not a monster",
+                self.name
+            ));
+        }
+        match self.speed {
+            Some(_) if !self.monster => problems.push(format!(
+                "\"{}\" has a speed but is not a monster",
+                self.name
+            )),
+            Some(speed) if speed < 1 => problems.push(format!(
+                "\"{}\" needs a speed of at least 1, not {}",
+                self.name, speed
+            )),
+            _ => {}
+        }
+        match self.morale {
+            Some(_) if !self.monster || self.fearless => problems.push(format!(
+                "\"{}\" has a morale but is not a monster that can flee",
+                self.name
+            )),
+            Some(morale) if morale < REGROUP_AT => problems.push(format!(
+                "\"{}\" needs a morale of at least {} to regroup after fleeing, not {}",
+                self.name, REGROUP_AT, morale
+            )),
+            _ => {}
+        }
+        if self.fearless && !self.monster {
+            problems.push(format!(
+                "\"{}\" is fearless but is not a monster",
+                self.name
+            ));
+        }
+        if !self.monster
+            && !(self.equipped.is_empty() && self.carried.is_empty() && self.spells.is_empty())
+        {
+            problems.push(format!(
+                "\"{}\" starts with items or spells but is not a monster",
+                self.name
+            ));
+        }
+        if let Some(loot) = &self.loot {
+            if !self.monster {
+                problems.push(format!("\"{}\" has loot but is not a monster", self.name));
+            }
+            if !raws.spawn_tables.contains_key(&loot.table) {
+                problems.push(format!(
+                    "\"{}\" drops loot from \"{}\", which is not a spawn table",
+                    self.name, loot.table
+                ));
+            }
+            if loot.chance < 1 || loot.chance > 100 {
+                problems.push(format!(
+                    "\"{}\" needs a loot chance from 1 to 100, not {}",
+                    self.name, loot.chance
+                ));
+            }
+        }
+        match &self.faction {
+            Some(_) if !self.monster => problems.push(format!(
+                "\"{}\" has a faction but is not a monster",
+                self.name
+            )),
+            Some(faction) if !raws.is_faction(faction) => problems.push(format!(
+                "\"{}\" is in faction \"{}\", which is not a faction",
+                self.name, faction
+            )),
+            _ => {}
+        }
+        if self.corpse && !self.monster {
+            problems.push(format!(
+                "\"{}\" leaves a corpse but is not a monster",
+                self.name
+            ));
+        }
+        self.equipped
+            .iter()
+            .filter(|name| {
+                raws.entity(name)
+                    .map_or(true, |raw| raw.equipment.is_none())
+            })
+            .for_each(|name| {
+                problems.push(format!(
+                    "\"{}\" is equipped with \"{}\", which is not equipment",
+                    self.name, name
+                ))
+            });
+        self.carried
+            .iter()
+            .filter(|name| raws.entity(name).map_or(true, |raw| !raw.is_item()))
+            .for_each(|name| {
+                problems.push(format!(
+                    "\"{}\" carries \"{}\", which is not an item",
+                    self.name, name
+                ))
+            });
+        self.spells
+            .iter()
+            .filter(|name| {
+                raws.entity(name).map_or(true, |raw| {
+                    raw.range.is_none()
+                        || (raw.inflicts_damage.is_none() && raw.inflicts_status.is_none())
+                })
+            })
+            .for_each(|name| {
+                problems.push(format!(
+                    "\"{}\" casts \"{}\", which is not a ranged attack",
+                    self.name, name
+                ))
+            });
+        if let Some(status) = &self.inflicts_status {
+            if status.min_turns < 1 || status.min_turns > status.max_turns {
+                problems.push(format!(
+                    "\"{}\" needs 1 <= min_turns <= max_turns for its status, not {} and {}",
+                    self.name, status.min_turns, status.max_turns
+                ));
+            }
+        }
+        problems
+    }
+}
+
+pub fn parse_raws(json: &str) -> Result<Raws, RawsError> {
+    let raws: Raws = serde_json::from_str(json).map_err(RawsError::Parse)?;
+    raws.validate()?;
+    Ok(raws)
+}
+
+/// Reads `RAWS_FILE`, or the built-in copy when there is no such file.
+pub fn load_raws() -> Result<(), RawsError> {
+    let json = match std::fs::read_to_string(RAWS_FILE) {
+        Ok(json) => json,
+        Err(_) if !std::path::Path::new(RAWS_FILE).exists() => EMBEDDED_RAWS.to_string(),
+        Err(err) => return Err(RawsError::Invalid(vec![err.to_string()])),
+    };
+    let raws = parse_raws(&json)?;
+    // Already set means raws were in use before loading; keep those
+    let _ = RAWS.set(raws);
+    Ok(())
+}
+
+pub fn raws() -> &'static Raws {
+    RAWS.get_or_init(|| parse_raws(EMBEDDED_RAWS).unwrap_or_else(|err| panic!("{}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_raws_are_valid() {
+        parse_raws(EMBEDDED_RAWS).unwrap();
+    }
+
+    #[test]
+    fn problems_are_named() {
+        let json = r#"{
+            "entities": [
+                { "name": "Imp", "renderable": { "glyph": "i", "order": "Second" }, "monster": true, "morale": 20 }
+            ],
+            "spawn_tables": { "room": [{ "name": "Gremlin", "weight": 1 }] }
+        }"#;
+        let msg = parse_raws(json).unwrap_err().to_string();
+        assert!(
+            msg.contains("\"Imp\" needs a renderable fg colour"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("monster \"Imp\" has no stats"), "{}", msg);
+        assert!(
+            msg.contains("\"Imp\" needs a morale of at least"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("names \"Gremlin\""), "{}", msg);
+    }
+}
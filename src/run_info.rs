@@ -0,0 +1,79 @@
+use bracket_lib::random::RandomNumberGenerator;
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+/// Seed given on the command line with `--seed`; it pre-fills the new game menu.
+pub static CLI_SEED: OnceCell<u64> = OnceCell::new();
+
+// Keep generated seeds short enough to read out in a bug report
+const MAX_RANDOM_SEED: u64 = 1_000_000_000;
+
+/// Information about the current run that isn't tied to any one level.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RunInfo {
+    pub seed: u64,
+    /// Number of turns the player has taken so far
+    pub turns: u64,
+    /// Save each time the player reaches a new level
+    pub autosave: bool,
+    /// The run's save is deleted when the player dies, and used up when loaded
+    pub permadeath: bool,
+    /// The slot this run was last saved to or loaded from
+    pub save_slot: Option<usize>,
+}
+
+impl RunInfo {
+    pub fn new(seed: u64) -> Self {
+        RunInfo {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// The one slot a permadeath run may be saved to, once it has been saved or loaded;
+    /// a copy elsewhere would cheat death.
+    pub fn permadeath_slot(&self) -> Option<usize> {
+        self.save_slot.filter(|_| self.permadeath)
+    }
+}
+
+pub fn random_seed() -> u64 {
+    RandomNumberGenerator::new().range(0, MAX_RANDOM_SEED)
+}
+
+/// The seed a new game starts with unless the player types another one.
+pub fn initial_seed() -> u64 {
+    CLI_SEED.get().copied().unwrap_or_else(random_seed)
+}
+
+/// Accepts both `--seed 1234` and `--seed=1234`.
+pub fn parse_seed_arg(args: impl IntoIterator<Item = String>) -> Option<u64> {
+    let args = args.into_iter().collect_vec();
+    args.iter()
+        .enumerate()
+        .find_map(|(ii, arg)| match arg.strip_prefix("--seed") {
+            Some("") => args.get(ii + 1).cloned(),
+            Some(rest) => rest.strip_prefix('=').map(|seed| seed.to_string()),
+            None => None,
+        })
+        .and_then(|seed| seed.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn seed_arg_is_parsed() {
+        assert_eq!(parse_seed_arg(args(&["--seed", "42"])), Some(42));
+        assert_eq!(parse_seed_arg(args(&["--seed=7"])), Some(7));
+        assert_eq!(parse_seed_arg(args(&["--seed"])), None);
+        assert_eq!(parse_seed_arg(args(&["--seed", "abc"])), None);
+        assert_eq!(parse_seed_arg(args(&[])), None);
+    }
+}
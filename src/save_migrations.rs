@@ -0,0 +1,458 @@
+use bracket_lib::random::RandomNumberGenerator;
+use serde_json::{Map as JsonMap, Value};
+
+use crate::{
+    components::{Faction, Initiative, Morale},
+    dungeon::MasterDungeonMap,
+    faction::{MONSTER_FACTION, PLAYER_FACTION},
+    gamelog::GameLog,
+    initiative_system::NORMAL_SPEED,
+    morale::DEFAULT_MORALE,
+    run_info::RunInfo,
+    saveload_system::SaveError,
+};
+
+/// Upgrades a save body from one format version to the next.
+type Migration = fn(Value) -> Result<Value, SaveError>;
+
+/// `MIGRATIONS[ii]` upgrades a version `ii + 1` body to version `ii + 2`.
+/// Version 0 saves predate the header and are converted by `upgrade_legacy_save`.
+const MIGRATIONS: &[Migration] = &[
+    add_run_modes,
+    add_dungeon,
+    add_affixes,
+    port_confusion,
+    add_verbose_log,
+    add_initiative,
+    add_ai_state,
+    add_morale,
+    add_factions,
+];
+
+/// Component storages in the order the version 0 format wrote them, one JSON array each.
+const LEGACY_COMPONENT_ORDER: [&str; 25] = [
+    "AreaOfEffect",
+    "BlocksTile",
+    "CombatStats",
+    "Confusion",
+    "Consumable",
+    "Equipped",
+    "EventIncomingDamage",
+    "EventWantsToDropItem",
+    "EventWantsToMelee",
+    "EventWantsToPickupItem",
+    "EventWantsToRemoveItem",
+    "EventWantsToUseItem",
+    "InBackpack",
+    "InflictsDamage",
+    "Item",
+    "Monster",
+    "Name",
+    "Player",
+    "Position",
+    "ProvidesHealing",
+    "Range",
+    "Renderable",
+    "SerializationHelper",
+    "Viewshed",
+    "WantsToUnequipItem",
+];
+
+/// Brings a body written with format `version` up to `current`.
+pub fn migrate(version: u32, current: u32, body: Value) -> Result<Value, SaveError> {
+    if version == 0 || version > current {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+    (version..current).try_fold(body, |body, from| {
+        let migration = MIGRATIONS
+            .get(from as usize - 1)
+            .ok_or(SaveError::UnsupportedVersion(from))?;
+        migration(body)
+    })
+}
+
+/// Converts a version 0 save, a bare stream of component arrays, into a version 1 body.
+pub fn upgrade_legacy_save(contents: &str) -> Result<Value, SaveError> {
+    let arrays = serde_json::Deserializer::from_str(contents)
+        .into_iter::<Value>()
+        .collect::<Result<Vec<Value>, _>>()?;
+    if arrays.len() != LEGACY_COMPONENT_ORDER.len() {
+        return Err(SaveError::Corrupt(format!(
+            "expected {} component lists in an old-style save, found {}",
+            LEGACY_COMPONENT_ORDER.len(),
+            arrays.len()
+        )));
+    }
+    let components: JsonMap<String, Value> = LEGACY_COMPONENT_ORDER
+        .iter()
+        .map(|name| name.to_string())
+        .zip(arrays)
+        .collect();
+    let mut body = serde_json::json!({ "components": components });
+
+    // Version 0 only kept the map; everything else about the run starts fresh
+    add_missing_fields(
+        &mut body,
+        "SerializationHelper",
+        "",
+        &[
+            ("run_info", serde_json::to_value(RunInfo::default())?),
+            ("rng", serde_json::to_value(RandomNumberGenerator::new())?),
+            ("log", serde_json::to_value(GameLog::default())?),
+        ],
+    );
+    Ok(body)
+}
+
+/// v1 -> v2: `RunInfo` gained the autosave and permadeath options and the run's save slot.
+fn add_run_modes(mut body: Value) -> Result<Value, SaveError> {
+    add_missing_fields(
+        &mut body,
+        "SerializationHelper",
+        "/run_info",
+        &[
+            ("autosave", Value::Bool(false)),
+            ("permadeath", Value::Bool(false)),
+            ("save_slot", Value::Null),
+        ],
+    );
+    Ok(body)
+}
+
+/// v2 -> v3: levels are kept once left, in a dungeon map saved alongside the current map.
+fn add_dungeon(mut body: Value) -> Result<Value, SaveError> {
+    add_missing_fields(
+        &mut body,
+        "SerializationHelper",
+        "",
+        &[(
+            "dungeon",
+            serde_json::to_value(MasterDungeonMap::default())?,
+        )],
+    );
+    Ok(body)
+}
+
+/// v3 -> v4: equipment gained an optional prefix and suffix affix.
+fn add_affixes(mut body: Value) -> Result<Value, SaveError> {
+    add_missing_fields(
+        &mut body,
+        "Item",
+        "/Equippable",
+        &[("prefix", Value::Null), ("suffix", Value::Null)],
+    );
+    Ok(body)
+}
+
+/// v4 -> v5: confusion became one status effect among many. Confusion scrolls now inflict
+/// it, and confused monsters have it, for as many turns as they had stumbling steps left.
+fn port_confusion(mut body: Value) -> Result<Value, SaveError> {
+    let components = match body
+        .pointer_mut("/components")
+        .and_then(Value::as_object_mut)
+    {
+        Some(components) => components,
+        None => return Ok(body),
+    };
+    let item_markers: Vec<Value> = components
+        .get("Item")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|entity| entity.get("marker").cloned())
+        .collect();
+    let (scrolls, confused): (Vec<Value>, Vec<Value>) = components
+        .remove("Confusion")
+        .and_then(|confusion| confusion.as_array().cloned())
+        .into_iter()
+        .flatten()
+        .partition(|entity| {
+            entity
+                .get("marker")
+                .map_or(false, |marker| item_markers.contains(marker))
+        });
+    let with_effect = |entity: &Value, wrap: fn(Value) -> Value| {
+        let turns = entity
+            .pointer("/components/0/step_sequence")
+            .and_then(Value::as_array)
+            .map_or(0, Vec::len);
+        let effect =
+            serde_json::json!({ "kind": "Confusion", "turns_left": turns, "magnitude": 1 });
+        serde_json::json!({
+            "marker": entity.get("marker").cloned().unwrap_or(Value::Null),
+            "components": [wrap(effect)],
+        })
+    };
+    components.insert(
+        "InflictsStatus".to_string(),
+        scrolls
+            .iter()
+            .map(|scroll| with_effect(scroll, |effect| serde_json::json!({ "effect": effect })))
+            .collect(),
+    );
+    components.insert(
+        "StatusEffects".to_string(),
+        confused
+            .iter()
+            // A monster with no steps left was about to recover
+            .filter(|entity| {
+                entity
+                    .pointer("/components/0/step_sequence")
+                    .and_then(Value::as_array)
+                    .map_or(false, |steps| !steps.is_empty())
+            })
+            .map(|victim| with_effect(victim, |effect| serde_json::json!({ "effects": [effect] })))
+            .collect(),
+    );
+    Ok(body)
+}
+
+/// v5 -> v6: the log can show combat rolls, which starts out off.
+fn add_verbose_log(mut body: Value) -> Result<Value, SaveError> {
+    add_missing_fields(
+        &mut body,
+        "SerializationHelper",
+        "/log",
+        &[("verbose", Value::Bool(false))],
+    );
+    Ok(body)
+}
+
+/// v6 -> v7: turns are scheduled by energy, so the player and monsters need initiative.
+/// Saved monsters all move at normal speed, whatever the raws now give their kind.
+fn add_initiative(mut body: Value) -> Result<Value, SaveError> {
+    let initiative = serde_json::to_value(Initiative::new(NORMAL_SPEED))?;
+    add_component_to(&mut body, &["Player", "Monster"], "Initiative", initiative);
+    Ok(body)
+}
+
+/// v7 -> v8: monsters remember where they last saw the player; saved ones start idle.
+fn add_ai_state(mut body: Value) -> Result<Value, SaveError> {
+    add_component_to(&mut body, &["Monster"], "AiState", Value::from("Idle"));
+    Ok(body)
+}
+
+/// v8 -> v9: monsters can lose their nerve and flee; saved ones get the default morale.
+fn add_morale(mut body: Value) -> Result<Value, SaveError> {
+    let morale = serde_json::to_value(Morale::new(DEFAULT_MORALE))?;
+    add_component_to(&mut body, &["Monster"], "Morale", morale);
+    Ok(body)
+}
+
+/// v9 -> v10: everyone belongs to a faction; saved monsters join the catch-all one.
+fn add_factions(mut body: Value) -> Result<Value, SaveError> {
+    let player = serde_json::to_value(Faction::new(PLAYER_FACTION))?;
+    add_component_to(&mut body, &["Player"], "Faction", player);
+    let monsters = serde_json::to_value(Faction::new(MONSTER_FACTION))?;
+    add_component_to(&mut body, &["Monster"], "Faction", monsters);
+    Ok(body)
+}
+
+/// Gives every entity with one of the `owners` components a new `component` set to `value`.
+fn add_component_to(body: &mut Value, owners: &[&str], component: &str, value: Value) {
+    if let Some(components) = body
+        .pointer_mut("/components")
+        .and_then(Value::as_object_mut)
+    {
+        let entities = owners
+            .iter()
+            .filter_map(|owner| components.get(*owner).and_then(Value::as_array))
+            .flatten()
+            .filter_map(|entity| entity.get("marker").cloned())
+            .map(|marker| serde_json::json!({ "marker": marker, "components": [value] }))
+            .collect::<Vec<Value>>();
+        if let Some(saved) = components
+            .entry(component)
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+        {
+            saved.extend(entities);
+        }
+    }
+}
+
+/// Gives every saved `component` whose object at `path` (a JSON pointer, "" for the
+/// component itself) lacks one of `defaults` that field's default value.
+pub fn add_missing_fields(
+    body: &mut Value,
+    component: &str,
+    path: &str,
+    defaults: &[(&str, Value)],
+) {
+    body.pointer_mut(&format!("/components/{}", component))
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|entity| entity.pointer_mut(&format!("/components/0{}", path)))
+        .filter_map(Value::as_object_mut)
+        .for_each(|fields| {
+            defaults.iter().for_each(|(field, default)| {
+                fields
+                    .entry(field.to_string())
+                    .or_insert_with(|| default.clone());
+            })
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_save() -> String {
+        LEGACY_COMPONENT_ORDER
+            .iter()
+            .map(|name| match *name {
+                "SerializationHelper" => {
+                    r#"[{"marker":[0],"components":[{"map":{}}]}]"#.to_string()
+                }
+                _ => "[]".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn legacy_save_gets_helper_defaults() {
+        let body = upgrade_legacy_save(&legacy_save()).unwrap();
+        let helper = body
+            .pointer("/components/SerializationHelper/0/components/0")
+            .unwrap();
+        assert!(helper.get("map").is_some());
+        assert!(helper.get("run_info").is_some());
+        assert!(helper.get("rng").is_some());
+        assert!(helper.get("log").is_some());
+    }
+
+    #[test]
+    fn v1_run_info_gets_run_modes() {
+        let body = serde_json::json!({ "components": { "SerializationHelper": [
+            { "marker": [0], "components": [{ "run_info": { "seed": 3, "turns": 9 } }] }
+        ]}});
+        let body = migrate(1, 3, body).unwrap();
+        let run_info: RunInfo = serde_json::from_value(
+            body.pointer("/components/SerializationHelper/0/components/0/run_info")
+                .unwrap()
+                .clone(),
+        )
+        .unwrap();
+        assert_eq!((run_info.seed, run_info.turns), (3, 9));
+        assert!(!run_info.autosave && !run_info.permadeath);
+        assert_eq!(run_info.save_slot, None);
+    }
+
+    #[test]
+    fn v3_equipment_gets_no_affixes() {
+        let body = serde_json::json!({ "components": { "Item": [
+            { "marker": [1], "components": ["Consumable"] },
+            { "marker": [2], "components": [{ "Equippable": { "quality": 2 } }] }
+        ]}});
+        let body = migrate(3, 4, body).unwrap();
+        assert_eq!(
+            body.pointer("/components/Item/1/components/0/Equippable"),
+            Some(&serde_json::json!({ "quality": 2, "prefix": null, "suffix": null }))
+        );
+        assert_eq!(
+            body.pointer("/components/Item/0/components/0"),
+            Some(&Value::from("Consumable"))
+        );
+    }
+
+    #[test]
+    fn v4_confusion_becomes_a_status_effect() {
+        let body = serde_json::json!({ "components": {
+            "Item": [{ "marker": [1], "components": ["Consumable"] }],
+            "Confusion": [
+                { "marker": [1], "components": [{ "step_sequence": [[0, 1], [1, 1], [-1, 0]] }] },
+                { "marker": [2], "components": [{ "step_sequence": [[0, 1]] }] }
+            ]
+        }});
+        let body = migrate(4, 5, body).unwrap();
+        let components = body.get("components").unwrap();
+        assert!(components.get("Confusion").is_none());
+        assert_eq!(
+            components.pointer("/InflictsStatus/0/components/0/effect/turns_left"),
+            Some(&Value::from(3))
+        );
+        assert_eq!(
+            components.pointer("/StatusEffects/0/marker"),
+            Some(&serde_json::json!([2]))
+        );
+        assert_eq!(
+            components.pointer("/StatusEffects/0/components/0/effects/0/kind"),
+            Some(&Value::from("Confusion"))
+        );
+    }
+
+    #[test]
+    fn v6_player_and_monsters_get_initiative() {
+        let body = serde_json::json!({ "components": {
+            "Player": [{ "marker": [1], "components": [{}] }],
+            "Monster": [{ "marker": [4], "components": [{}] }]
+        }});
+        let body = migrate(6, 7, body).unwrap();
+        let initiative = serde_json::json!({ "speed": NORMAL_SPEED, "energy": 0 });
+        assert_eq!(
+            body.pointer("/components/Initiative"),
+            Some(&serde_json::json!([
+                { "marker": [1], "components": [initiative] },
+                { "marker": [4], "components": [initiative] }
+            ]))
+        );
+    }
+
+    #[test]
+    fn v7_monsters_start_idle() {
+        let body = serde_json::json!({ "components": {
+            "Player": [{ "marker": [1], "components": [{}] }],
+            "Monster": [{ "marker": [4], "components": [{}] }]
+        }});
+        let body = migrate(7, 8, body).unwrap();
+        assert_eq!(
+            body.pointer("/components/AiState"),
+            Some(&serde_json::json!([{ "marker": [4], "components": ["Idle"] }]))
+        );
+    }
+
+    #[test]
+    fn v8_monsters_get_default_morale() {
+        let body = serde_json::json!({ "components": {
+            "Monster": [{ "marker": [4], "components": [{}] }]
+        }});
+        let body = migrate(8, 9, body).unwrap();
+        assert_eq!(
+            body.pointer("/components/Morale/0/components/0"),
+            Some(&serde_json::json!({ "base": DEFAULT_MORALE, "current": DEFAULT_MORALE }))
+        );
+    }
+
+    #[test]
+    fn v9_everyone_joins_a_faction() {
+        let body = serde_json::json!({ "components": {
+            "Player": [{ "marker": [1], "components": [{}] }],
+            "Monster": [{ "marker": [4], "components": [{}] }]
+        }});
+        let body = migrate(9, 10, body).unwrap();
+        assert_eq!(
+            body.pointer("/components/Faction"),
+            Some(&serde_json::json!([
+                { "marker": [1], "components": [{ "name": PLAYER_FACTION }] },
+                { "marker": [4], "components": [{ "name": MONSTER_FACTION }] }
+            ]))
+        );
+    }
+
+    #[test]
+    fn truncated_legacy_save_is_rejected() {
+        assert!(matches!(
+            upgrade_legacy_save("[][]"),
+            Err(SaveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn newer_saves_are_rejected() {
+        assert!(matches!(
+            migrate(2, 1, Value::Null),
+            Err(SaveError::UnsupportedVersion(2))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::saveload_system::SaveError;
+
+/// Where save files are kept: plain files natively, `localStorage` in the browser.
+pub trait SaveStorage {
+    fn read(&self, key: &str) -> Result<String, SaveError>;
+    fn write(&mut self, key: &str, contents: &str) -> Result<(), SaveError>;
+    fn delete(&mut self, key: &str) -> Result<(), SaveError>;
+    fn exists(&self, key: &str) -> bool;
+
+    /// The first line of `key`, for previews; backends may avoid reading the rest.
+    fn read_first_line(&self, key: &str) -> Result<String, SaveError> {
+        self.read(key)
+            .map(|contents| contents.lines().next().unwrap_or_default().to_string())
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), SaveError> {
+        let contents = self.read(from)?;
+        self.write(to, &contents)?;
+        self.delete(from)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type PlatformStorage = FileStorage;
+#[cfg(target_arch = "wasm32")]
+pub type PlatformStorage = LocalStorage;
+
+/// Saves as files in the working directory, named by key.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub struct FileStorage {}
+
+// Headers are a few hundred bytes; old headerless saves are one very long line
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_FIRST_LINE_BYTES: u64 = 4096;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SaveStorage for FileStorage {
+    fn read(&self, key: &str) -> Result<String, SaveError> {
+        Ok(std::fs::read_to_string(key)?)
+    }
+
+    fn write(&mut self, key: &str, contents: &str) -> Result<(), SaveError> {
+        Ok(std::fs::write(key, contents)?)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), SaveError> {
+        Ok(std::fs::remove_file(key)?)
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        std::path::Path::new(key).exists()
+    }
+
+    fn read_first_line(&self, key: &str) -> Result<String, SaveError> {
+        use std::io::{BufRead, BufReader, Read};
+        let mut first_line = String::new();
+        BufReader::new(std::fs::File::open(key)?)
+            .take(MAX_FIRST_LINE_BYTES)
+            .read_line(&mut first_line)?;
+        Ok(first_line)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<(), SaveError> {
+        Ok(std::fs::rename(from, to)?)
+    }
+}
+
+/// Saves in the browser's `localStorage`, so they survive a page refresh.
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+pub struct LocalStorage {}
+
+#[cfg(target_arch = "wasm32")]
+const LOCAL_STORAGE_PREFIX: &str = "rusty_rogue/";
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    fn storage() -> Result<web_sys::Storage, SaveError> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| storage_error("localStorage is not available"))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SaveStorage for LocalStorage {
+    fn read(&self, key: &str) -> Result<String, SaveError> {
+        Self::storage()?
+            .get_item(&format!("{}{}", LOCAL_STORAGE_PREFIX, key))
+            .map_err(|_| storage_error("could not read from localStorage"))?
+            .ok_or_else(|| not_found(key))
+    }
+
+    fn write(&mut self, key: &str, contents: &str) -> Result<(), SaveError> {
+        // Fails if the save would go over the browser's storage quota
+        Self::storage()?
+            .set_item(&format!("{}{}", LOCAL_STORAGE_PREFIX, key), contents)
+            .map_err(|_| storage_error("localStorage is full or disabled"))
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), SaveError> {
+        Self::storage()?
+            .remove_item(&format!("{}{}", LOCAL_STORAGE_PREFIX, key))
+            .map_err(|_| storage_error("could not remove from localStorage"))
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.read(key).is_ok()
+    }
+}
+
+/// Keeps saves in memory; lets tests save and load without touching the disk.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: std::collections::HashMap<String, String>,
+}
+
+#[cfg(test)]
+impl SaveStorage for MemoryStorage {
+    fn read(&self, key: &str) -> Result<String, SaveError> {
+        self.files.get(key).cloned().ok_or_else(|| not_found(key))
+    }
+
+    fn write(&mut self, key: &str, contents: &str) -> Result<(), SaveError> {
+        self.files.insert(key.to_string(), contents.to_string());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), SaveError> {
+        self.files
+            .remove(key)
+            .map(|_| ())
+            .ok_or_else(|| not_found(key))
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.files.contains_key(key)
+    }
+}
+
+#[cfg(any(test, target_arch = "wasm32"))]
+fn not_found(key: &str) -> SaveError {
+    SaveError::Io(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} does not exist", key),
+    ))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn storage_error(why: &str) -> SaveError {
+    SaveError::Io(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        why.to_string(),
+    ))
+}
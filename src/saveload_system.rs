@@ -1,117 +1,473 @@
-#[cfg(not(target_arch = "wasm32"))]
-use std::fs::File;
-use std::{
-    fs::{self},
-    path::Path,
-};
+use std::{collections::BTreeMap, fmt};
 
+use bracket_lib::random::RandomNumberGenerator;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use specs::{prelude::*, saveload::*, World, WorldExt};
 
-use crate::execute_with_type_list;
-use crate::{components::*, delete_state};
+use crate::{
+    components::*, delete_state, dungeon::MasterDungeonMap, gamelog::GameLog, map::Map,
+    run_info::RunInfo, save_migrations, save_storage::SaveStorage,
+};
+use crate::{execute_with_type_list, register_individually};
+
+pub const SAVE_SLOTS: usize = 5;
+// Where saves went before there were slots; it becomes the first slot
+const LEGACY_SAVE_FILE: &str = "savegame.json";
+
+/// Bump this, and add a migration in `save_migrations`, whenever a saved type changes shape.
+pub const SAVE_FORMAT_VERSION: u32 = 10;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    Corrupt(String),
+    PermadeathSlot(usize),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, ff: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(er) => write!(ff, "could not access the save file ({})", er),
+            SaveError::Json(er) => write!(ff, "the save file is not valid ({})", er),
+            SaveError::UnsupportedVersion(version) => {
+                write!(ff, "save format version {} is not supported", version)
+            }
+            SaveError::Corrupt(why) => write!(ff, "the save file is damaged ({})", why),
+            SaveError::PermadeathSlot(slot) => {
+                write!(
+                    ff,
+                    "a permadeath run can only be saved to slot {}",
+                    slot + 1
+                )
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(er: std::io::Error) -> Self {
+        SaveError::Io(er)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(er: serde_json::Error) -> Self {
+        SaveError::Json(er)
+    }
+}
+
+/// First line of a save file; it is read before anything else is trusted, and is
+/// all the save slot menu reads. Only the version is required; the rest is just for show.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SaveHeader {
+    pub version: u32,
+    #[serde(default)]
+    pub depth: i32,
+    #[serde(default)]
+    pub hp: u16,
+    #[serde(default)]
+    pub max_hp: u16,
+    #[serde(default)]
+    pub turns: u64,
+    #[serde(default)]
+    pub seed: u64,
+    /// Seconds since the Unix epoch
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub permadeath: bool,
+}
+
+impl SaveHeader {
+    fn describe(ecs: &World) -> SaveHeader {
+        let run_info = ecs.fetch::<RunInfo>();
+        let (hp, max_hp) = (
+            &ecs.read_storage::<Player>(),
+            &ecs.read_storage::<CombatStats>(),
+        )
+            .join()
+            .next()
+            .map_or((0, 0), |(_, stats)| (stats.hp, stats.max_hp));
+        SaveHeader {
+            version: SAVE_FORMAT_VERSION,
+            depth: ecs.fetch::<Map>().depth,
+            hp,
+            max_hp,
+            turns: run_info.turns,
+            seed: run_info.seed,
+            timestamp: now_timestamp(),
+            permadeath: run_info.permadeath,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SlotSummary {
+    Empty,
+    Saved(SaveHeader),
+    /// Written before saves had a header, so there is nothing to preview
+    OldFormat,
+    Unreadable,
+}
 
-const SAVE_FILE: &str = "savegame.json";
+impl SlotSummary {
+    pub fn is_empty(&self) -> bool {
+        matches!(self, SlotSummary::Empty)
+    }
+}
+
+/// What is in each save slot, as last scanned; refreshed whenever the slot menu opens.
+#[derive(Default)]
+pub struct SaveSlots {
+    pub summaries: Vec<SlotSummary>,
+}
+
+impl SaveSlots {
+    pub fn scan(storage: &mut dyn SaveStorage) -> SaveSlots {
+        adopt_legacy_save(storage);
+        SaveSlots {
+            summaries: (0..SAVE_SLOTS)
+                .map(|slot| read_slot_summary(storage, slot))
+                .collect(),
+        }
+    }
+}
+
+fn slot_file(slot: usize) -> String {
+    format!("savegame_{}.json", slot + 1)
+}
+
+fn adopt_legacy_save(storage: &mut dyn SaveStorage) {
+    if storage.exists(LEGACY_SAVE_FILE) && !storage.exists(&slot_file(0)) {
+        // If this fails the old save just stays where it was
+        let _ = storage.rename(LEGACY_SAVE_FILE, &slot_file(0));
+    }
+}
+
+fn read_slot_summary(storage: &dyn SaveStorage, slot: usize) -> SlotSummary {
+    if !storage.exists(&slot_file(slot)) {
+        return SlotSummary::Empty;
+    }
+    match storage.read_first_line(&slot_file(slot)) {
+        // Headerless saves open straight into an array
+        Ok(first_line) if first_line.starts_with('[') => SlotSummary::OldFormat,
+        Ok(first_line) => {
+            serde_json::from_str(&first_line).map_or(SlotSummary::Unreadable, SlotSummary::Saved)
+        }
+        Err(_) => SlotSummary::Unreadable,
+    }
+}
 
 #[cfg(not(target_arch = "wasm32"))]
+fn now_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now_timestamp() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// Serialized component storages, keyed by component type name.
+#[derive(Serialize, Deserialize, Default)]
+struct SaveBody {
+    components: BTreeMap<String, Value>,
+}
+
 macro_rules! serialize_individually {
-  ($ecs:expr, $ser:expr, $data:expr, $( $type:ty),*, $(,)?) => {
+  ($ecs:expr, $body:expr, $data:expr, $( $type:ty),*, $(,)?) => {
       $(
-      SerializeComponents::<NoError, SimpleMarker<SerializeMe>>::serialize(
+      let component_data = SerializeComponents::<NoError, SimpleMarker<SerializeMe>>::serialize(
           &( $ecs.read_storage::<$type>(), ),
           &$data.0,
           &$data.1,
-          &mut $ser,
-      )
-      .unwrap();
+          serde_json::value::Serializer,
+      )?;
+      $body.components.insert(stringify!($type).to_string(), component_data);
       )*
   };
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn save_game(_ecs: &mut World) {
-    bracket_lib::terminal::console::log("Saving is not supported on the web");
-}
-
-#[cfg(not(target_arch = "wasm32"))]
-pub fn save_game(ecs: &mut World) {
-    //let map_data = serde_json::to_string(&*ecs.fetch::<Map>()).unwrap();
-    //println!("map data:\n{}", map_data);
-
-    let mapcopy = ecs.get_mut::<super::map::Map>().unwrap().clone();
-    let save_helper = ecs
-        .create_entity()
-        .with(SerializationHelper { map: mapcopy })
-        .marked::<SimpleMarker<SerializeMe>>()
-        .build();
+fn serialize_world(ecs: &World) -> Result<String, SaveError> {
+    let mut body = SaveBody::default();
     {
         let data = (
             ecs.entities(),
             ecs.read_storage::<SimpleMarker<SerializeMe>>(),
         );
+        execute_with_type_list!(serialize_individually!(ecs, body, data));
+    }
+    let header = SaveHeader::describe(ecs);
+    Ok(format!(
+        "{}\n{}",
+        serde_json::to_string(&header)?,
+        serde_json::to_string(&body)?
+    ))
+}
 
-        let writer = File::create(SAVE_FILE).unwrap();
-        let mut serializer = serde_json::Serializer::new(writer);
-        execute_with_type_list!(serialize_individually!(ecs, serializer, data));
+pub fn save_game(
+    ecs: &mut World,
+    storage: &mut dyn SaveStorage,
+    slot: usize,
+) -> Result<(), SaveError> {
+    if let Some(permadeath_slot) = ecs.fetch::<RunInfo>().permadeath_slot() {
+        if permadeath_slot != slot {
+            return Err(SaveError::PermadeathSlot(permadeath_slot));
+        }
     }
+    ecs.write_resource::<RunInfo>().save_slot = Some(slot);
+    let mapcopy = ecs.get_mut::<Map>().unwrap().clone();
+    let run_info = ecs.fetch::<RunInfo>().clone();
+    let rng = ecs.fetch::<RandomNumberGenerator>().clone();
+    let log = ecs.fetch::<GameLog>().clone();
+    let dungeon = ecs.fetch::<MasterDungeonMap>().clone();
+    let save_helper = ecs
+        .create_entity()
+        .with(SerializationHelper {
+            map: mapcopy,
+            run_info,
+            rng,
+            log,
+            dungeon,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+
+    let contents = serialize_world(ecs);
 
     ecs.delete_entity(save_helper)
         .unwrap_or_else(|_| panic!("Unable to delete serialization helper entity"));
+    storage.write(&slot_file(slot), &contents?)
+}
+
+pub fn first_free_slot(storage: &dyn SaveStorage) -> Option<usize> {
+    (0..SAVE_SLOTS).find(|slot| !storage.exists(&slot_file(*slot)))
 }
 
-pub fn does_save_exist() -> bool {
-    Path::new(SAVE_FILE).exists()
+pub fn delete_save(storage: &mut dyn SaveStorage, slot: usize) -> Result<(), SaveError> {
+    storage.delete(&slot_file(slot))
+}
+
+pub fn does_save_exist(storage: &dyn SaveStorage) -> bool {
+    storage.exists(LEGACY_SAVE_FILE) || (0..SAVE_SLOTS).any(|slot| storage.exists(&slot_file(slot)))
 }
 
 // loading
 
 macro_rules! deserialize_individually {
-  ($ecs:expr, $de_ser:expr, $data:expr, $( $type:ty),* $(,)?) => {
+  ($ecs:expr, $body:expr, $data:expr, $( $type:ty),* $(,)?) => {
       $(
-      DeserializeComponents::<NoError, _>::deserialize(
-          &mut ( &mut $ecs.write_storage::<$type>(), ),
-          &$data.0, // entities
-          &mut $data.1, // marker
-          &mut $data.2, // allocater
-          &mut $de_ser,
-      )
-      .unwrap();
+      // Storages added since the save was written simply start out empty
+      if let Some(component_data) = $body.components.get(stringify!($type)) {
+          DeserializeComponents::<NoError, _>::deserialize(
+              &mut ( &mut $ecs.write_storage::<$type>(), ),
+              &$data.0, // entities
+              &mut $data.1, // marker
+              &mut $data.2, // allocater
+              component_data,
+          )
+          .map_err(|er| SaveError::Corrupt(format!("{}: {}", stringify!($type), er)))?;
+      }
       )*
   };
 }
 
-pub fn load_game(ecs: &mut World) {
-    // Delete everything
-    delete_state(ecs);
-    let save_file_contents = fs::read_to_string(SAVE_FILE)
-        .unwrap_or_else(|_| panic!("Unable to read file {}", SAVE_FILE));
-    let mut de_ser = serde_json::Deserializer::from_str(&save_file_contents);
-    {
-        let mut de_ser_reqs = (
-            ecs.entities(),
-            ecs.write_storage::<SimpleMarker<SerializeMe>>(),
-            ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
-        );
-        execute_with_type_list!(deserialize_individually!(ecs, de_ser, de_ser_reqs));
-    }
+/// Reads the header and upgrades the body to the current format, without touching the world.
+fn parse_save(contents: &str) -> Result<SaveBody, SaveError> {
+    let (first_line, rest) = contents.split_once('\n').unwrap_or((contents, ""));
+    // Saves from before the header existed start straight into the component lists
+    let body = if first_line.starts_with('[') {
+        save_migrations::migrate(
+            1,
+            SAVE_FORMAT_VERSION,
+            save_migrations::upgrade_legacy_save(contents)?,
+        )?
+    } else {
+        let header = serde_json::from_str::<SaveHeader>(first_line)
+            .map_err(|er| SaveError::Corrupt(format!("unreadable header: {}", er)))?;
+        save_migrations::migrate(
+            header.version,
+            SAVE_FORMAT_VERSION,
+            serde_json::from_str(rest)?,
+        )?
+    };
+    Ok(serde_json::from_value(body)?)
+}
+
+fn deserialize_world(ecs: &mut World, body: &SaveBody) -> Result<(), SaveError> {
+    let mut de_ser_reqs = (
+        ecs.entities(),
+        ecs.write_storage::<SimpleMarker<SerializeMe>>(),
+        ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
+    );
+    execute_with_type_list!(deserialize_individually!(ecs, body, de_ser_reqs));
+    Ok(())
+}
 
-    let ser_helper_vec: Vec<Entity> = {
-        let entities = ecs.entities();
-        let helper = ecs.read_storage::<SerializationHelper>();
-        (&entities, &helper)
+/// Removes the serialization helper from the world, so we don't keep an extra copy of it
+/// (and its contents) each time we save.
+fn take_helper(ecs: &mut World) -> Result<SerializationHelper, SaveError> {
+    let helpers: Vec<(Entity, SerializationHelper)> =
+        (&ecs.entities(), &ecs.read_storage::<SerializationHelper>())
             .join()
-            .map(|(ent, help)| {
-                // load the map
-                let mut worldmap = ecs.write_resource::<super::map::Map>();
-                *worldmap = help.map.clone();
-                worldmap.tile_content = vec![Vec::new(); worldmap.tile_count()];
-                ent
-            })
-            .collect()
-    };
-    // Delete serialization helper, so we don't keep an extra copy of it (and its contents)
-    // each time we save.
-    ser_helper_vec.into_iter().for_each(|help| {
-        ecs.delete_entity(help)
+            .map(|(ent, help)| (ent, help.clone()))
+            .collect();
+    helpers.iter().for_each(|(ent, _)| {
+        ecs.delete_entity(*ent)
             .unwrap_or_else(|er| panic!("Unable to delete helper: {}", er))
     });
+    helpers
+        .into_iter()
+        .next()
+        .map(|(_, help)| help)
+        .ok_or_else(|| SaveError::Corrupt("no map was saved".to_string()))
+}
+
+/// Loads everything into a throwaway world first, so a bad save never clobbers the running game.
+fn check_save_loads(body: &SaveBody) -> Result<SerializationHelper, SaveError> {
+    let mut scratch = World::new();
+    execute_with_type_list!(register_individually!(scratch));
+    scratch.register::<SimpleMarker<SerializeMe>>();
+    scratch.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+    deserialize_world(&mut scratch, body)?;
+    take_helper(&mut scratch)
+}
+
+/// Loading a permadeath run uses its save up, so there is no going back to it.
+pub fn load_game(
+    ecs: &mut World,
+    storage: &mut dyn SaveStorage,
+    slot: usize,
+) -> Result<(), SaveError> {
+    let save_file_contents = storage.read(&slot_file(slot))?;
+    let body = parse_save(&save_file_contents)?;
+    if check_save_loads(&body)?.run_info.permadeath {
+        storage.delete(&slot_file(slot))?;
+    }
+
+    // Delete everything
+    delete_state(ecs);
+    deserialize_world(ecs, &body)?;
+    let help = take_helper(ecs)?;
+
+    // load the map
+    let mut worldmap = ecs.write_resource::<Map>();
+    *worldmap = help.map;
+    worldmap.tile_content = vec![Vec::new(); worldmap.tile_count()];
+    // restore the run: message history, metadata, and where we are in the random stream
+    *ecs.write_resource::<RunInfo>() = RunInfo {
+        save_slot: Some(slot),
+        ..help.run_info
+    };
+    *ecs.write_resource::<RandomNumberGenerator>() = help.rng;
+    *ecs.write_resource::<GameLog>() = help.log;
+    *ecs.write_resource::<MasterDungeonMap>() = help.dungeon;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        init_state,
+        player::{get_player_unwrap, PLAYER_NAME},
+        save_storage::MemoryStorage,
+    };
+
+    fn player_hp(ecs: &World) -> u16 {
+        let player = get_player_unwrap(ecs, PLAYER_NAME);
+        ecs.read_storage::<CombatStats>().get(player).unwrap().hp
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let (mut gs, _) = init_state(true, None);
+        let mut storage = MemoryStorage::default();
+        let saved_hp = player_hp(&gs.ecs);
+        save_game(&mut gs.ecs, &mut storage, 2).unwrap();
+
+        let player = get_player_unwrap(&gs.ecs, PLAYER_NAME);
+        gs.ecs
+            .write_storage::<CombatStats>()
+            .get_mut(player)
+            .unwrap()
+            .hp = 1;
+        load_game(&mut gs.ecs, &mut storage, 2).unwrap();
+        assert_eq!(player_hp(&gs.ecs), saved_hp);
+
+        let slots = SaveSlots::scan(&mut storage);
+        assert!(slots.summaries[0].is_empty());
+        assert!(matches!(&slots.summaries[2], SlotSummary::Saved(header) if header.hp == saved_hp));
+    }
+
+    #[test]
+    fn turns_in_progress_are_not_saved() {
+        let (mut gs, _) = init_state(true, None);
+        let mut storage = MemoryStorage::default();
+        let player = get_player_unwrap(&gs.ecs, PLAYER_NAME);
+        gs.ecs
+            .write_storage::<MyTurn>()
+            .insert(player, MyTurn {})
+            .unwrap();
+        save_game(&mut gs.ecs, &mut storage, 0).unwrap();
+
+        let body = parse_save(&storage.read(&slot_file(0)).unwrap()).unwrap();
+        assert!(!body.components.contains_key("MyTurn"));
+    }
+
+    #[test]
+    fn permadeath_save_is_used_up_by_loading() {
+        let (mut gs, _) = init_state(true, None);
+        let mut storage = MemoryStorage::default();
+        gs.ecs.write_resource::<RunInfo>().permadeath = true;
+        save_game(&mut gs.ecs, &mut storage, 0).unwrap();
+        assert_eq!(first_free_slot(&storage), Some(1));
+
+        load_game(&mut gs.ecs, &mut storage, 0).unwrap();
+        assert_eq!(first_free_slot(&storage), Some(0));
+        assert_eq!(gs.ecs.fetch::<RunInfo>().save_slot, Some(0));
+    }
+
+    #[test]
+    fn permadeath_run_has_one_save() {
+        let (mut gs, _) = init_state(true, None);
+        let mut storage = MemoryStorage::default();
+        gs.ecs.write_resource::<RunInfo>().permadeath = true;
+        save_game(&mut gs.ecs, &mut storage, 1).unwrap();
+
+        assert!(matches!(
+            save_game(&mut gs.ecs, &mut storage, 2),
+            Err(SaveError::PermadeathSlot(1))
+        ));
+        assert!(!storage.exists(&slot_file(2)));
+        save_game(&mut gs.ecs, &mut storage, 1).unwrap();
+    }
+
+    #[test]
+    fn damaged_save_leaves_world_alone() {
+        let (mut gs, _) = init_state(true, None);
+        let mut storage = MemoryStorage::default();
+        storage
+            .write(&slot_file(0), "{\"version\":1}\n{\"components\":{}}")
+            .unwrap();
+        let player = get_player_unwrap(&gs.ecs, PLAYER_NAME);
+        assert!(matches!(
+            load_game(&mut gs.ecs, &mut storage, 0),
+            Err(SaveError::Corrupt(_))
+        ));
+        assert!(gs.ecs.is_alive(player));
+    }
+
+    #[test]
+    fn header_without_version_is_corrupt() {
+        ["{}", "{\"depth\":3}"].into_iter().for_each(|header| {
+            let contents = format!("{}\n{{\"components\":{{}}}}", header);
+            assert!(matches!(parse_save(&contents), Err(SaveError::Corrupt(_))));
+        });
+    }
 }
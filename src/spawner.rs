@@ -1,20 +1,23 @@
 use bracket_lib::{random::RandomNumberGenerator, terminal::*};
-use itertools::Itertools;
 use specs::{
     prelude::*,
     saveload::{MarkedBuilder, SimpleMarker},
 };
 
-use crate::util::*;
-use crate::{components::*, equipment::*, map::Map, random_table::*, rect::Rect, State};
-use EquipmentType::*;
-use MeleeWeaponType::*;
-use WeaponType::*;
+use crate::raws::{raws, RawEntity, RawEquipment, ROOM_TABLE};
+use crate::{
+    components::*,
+    equipment::*,
+    faction::{MONSTER_FACTION, PLAYER_FACTION},
+    initiative_system::NORMAL_SPEED,
+    map::Map,
+    morale::DEFAULT_MORALE,
+    random_table::*,
+    State,
+};
 
 const INIT_MAX_SPAWN: u16 = 5;
 
-type SimpleSpawner<'a> = dyn CloneableFnAB<&'a mut World, Position, Entity> + 'a;
-
 struct WorldEntityData {
     name: String,
     renderable: Renderable,
@@ -62,97 +65,321 @@ fn combat_entity(
     sentient_entity(ecs, pos, base_data, view_range_opt).with(combat_stats)
 }
 
-fn non_blocking_entity(
-    ecs: &mut World,
-    pos: Position,
-    base_data: WorldEntityData,
-) -> EntityBuilder {
-    base_renderable_entity(ecs, Some(pos), base_data)
+fn rgb(color: [u8; 3]) -> RGB {
+    RGB::from_u8(color[0], color[1], color[2])
 }
 
-fn consumable_entity(ecs: &mut World, pos: Position, base_data: WorldEntityData) -> EntityBuilder {
-    non_blocking_entity(ecs, pos, base_data)
-        .with(Item::Consumable)
-        .with(Consumable {})
+fn with_opt<C: Component + Send + Sync>(
+    builder: EntityBuilder,
+    comp_opt: Option<C>,
+) -> EntityBuilder {
+    match comp_opt {
+        Some(comp) => builder.with(comp),
+        None => builder,
+    }
 }
 
-fn equippable_entity(
-    ecs: &mut World,
-    pos: Position,
-    base_data: WorldEntityData,
-    item: Equipment,
-) -> EntityBuilder {
-    non_blocking_entity(ecs, pos, base_data).with(Item::Equippable(item))
+/// Rolls on weighted choices; `None` if every weight is zero.
+fn roll_weighted<T: Clone>(
+    rng: &mut RandomNumberGenerator,
+    choices: impl Iterator<Item = (T, u16)>,
+) -> Option<T> {
+    choices
+        .filter(|(_, weight)| *weight > 0)
+        .fold(
+            None,
+            |table_opt: Option<RandomTable<T>>, (choice, weight)| {
+                Some(match table_opt {
+                    None => RandomTable::new(choice, weight),
+                    Some(table) => table.add(choice, weight),
+                })
+            },
+        )
+        .map(|table| table.roll(rng))
+}
+
+fn roll_equipment(
+    rng: &mut RandomNumberGenerator,
+    raw_equipment: &RawEquipment,
+    map_depth: i32,
+) -> Option<Equipment> {
+    let material_table = raws().material_tables.get(&raw_equipment.material_table)?;
+    let material = roll_weighted(
+        rng,
+        material_table
+            .iter()
+            .map(|entry| (entry.material.clone(), entry.weight_at(map_depth))),
+    )?;
+    let equipment = Equipment::new(
+        raw_equipment.slots.clone(),
+        raw_equipment.kind.equipment_type(),
+        material,
+        random_quality(rng, map_depth),
+    );
+    Some(roll_affixes(rng, equipment, map_depth))
+}
+
+/// Gives `equipment` a chance at a prefix and at a suffix that suit it, better with depth.
+fn roll_affixes(
+    rng: &mut RandomNumberGenerator,
+    equipment: Equipment,
+    map_depth: i32,
+) -> Equipment {
+    [true, false]
+        .into_iter()
+        .fold(equipment, |equipment, is_prefix| {
+            let fitting = AFFIXES
+                .iter()
+                .filter(|affix| affix.is_prefix() == is_prefix && affix.fits(&equipment))
+                .map(|affix| (Some(*affix), affix.weight_at(map_depth)));
+            let affix_opt =
+                roll_weighted(rng, std::iter::once((None, NO_AFFIX_WEIGHT)).chain(fitting))
+                    .flatten();
+            match affix_opt {
+                Some(affix) => equipment.with_affix(affix),
+                None => equipment,
+            }
+        })
 }
 
-pub fn dagger_at_level(map_depth: i32, ecs: &mut World, pos: Position) -> Entity {
-    let (dagger_material, dagger_quality) = {
+/// Spawns the raws entity called `name`; equipment rolls its material and quality for `map_depth`.
+pub fn spawn_named(ecs: &mut World, name: &str, pos: Position, map_depth: i32) -> Option<Entity> {
+    let raw = raws().entity(name)?;
+    let (equipment_opt, inflicts_status_opt, ammunition_opt) = {
         let rng = &mut ecs.write_resource::<RandomNumberGenerator>();
-        (
-            random_blade_material(rng, map_depth),
-            random_quality(rng, map_depth),
-        )
+        let equipment_opt = match &raw.equipment {
+            Some(raw_equipment) => Some(roll_equipment(rng, raw_equipment, map_depth)?),
+            None => None,
+        };
+        let inflicts_status_opt = raw.inflicts_status.as_ref().map(|status| InflictsStatus {
+            effect: StatusEffect {
+                kind: status.kind,
+                turns_left: rng.range(status.min_turns, status.max_turns + 1),
+                magnitude: status.magnitude,
+            },
+        });
+        let ammunition_opt = raw.ammunition.as_ref().map(|ammunition| Ammunition {
+            kind: ammunition.kind.clone(),
+            count: rng.range(ammunition.min_count, ammunition.max_count + 1),
+        });
+        (equipment_opt, inflicts_status_opt, ammunition_opt)
+    };
+    if raw.blocks_tile {
+        let mut map = ecs.write_resource::<Map>();
+        let pos_ix = map.pos_idx(pos);
+        map.blocked[pos_ix] = true;
+    }
+
+    let fg = raw
+        .renderable
+        .fg
+        .map(rgb)
+        .or_else(|| {
+            equipment_opt
+                .as_ref()
+                .map(|eq| RGB::named(eq.material.color()))
+        })
+        .unwrap_or_else(|| RGB::named(WHITE));
+    let base_data = WorldEntityData {
+        name: equipment_opt
+            .as_ref()
+            .map(Equipment::name)
+            .or_else(|| ammunition_opt.as_ref().map(Ammunition::name))
+            .unwrap_or_else(|| raw.name.clone()),
+        renderable: Renderable {
+            glyph: bracket_lib::prelude::to_cp437(raw.renderable.glyph),
+            fg,
+            bg: rgb(raw.renderable.bg),
+            render_order: raw.renderable.order,
+        },
     };
-    let eq_item = Equipment::new(
-        ONE_HANDED,
-        Weapon(Melee(Dagger)),
-        dagger_material.clone(),
-        dagger_quality,
+    let builder = base_renderable_entity(ecs, Some(pos), base_data);
+    let builder = with_opt(
+        builder,
+        raw.view_range.map(|range| Viewshed {
+            visible_tiles: Vec::new(),
+            range: ViewRange(range),
+            dirty: true,
+        }),
     );
-    equippable_entity(
+    let builder = with_opt(
+        builder,
+        raw.stats.as_ref().map(|stats| CombatStats {
+            max_hp: stats.max_hp,
+            hp: stats.max_hp,
+            defense: stats.defense,
+            power: stats.power,
+        }),
+    );
+    let builder = with_opt(builder, raw.monster.then_some(Monster {}));
+    let builder = with_opt(
+        builder,
+        raw.monster
+            .then(|| Initiative::new(raw.speed.unwrap_or(NORMAL_SPEED))),
+    );
+    let builder = with_opt(builder, raw.monster.then_some(AiState::Idle));
+    let builder = with_opt(
+        builder,
+        raw.monster
+            .then(|| Faction::new(raw.faction.as_deref().unwrap_or(MONSTER_FACTION))),
+    );
+    let builder = with_opt(
+        builder,
+        (raw.monster && !raw.fearless).then(|| Morale::new(raw.morale.unwrap_or(DEFAULT_MORALE))),
+    );
+    let builder = with_opt(
+        builder,
+        raw.loot.as_ref().map(|loot| LootTable {
+            table: loot.table.clone(),
+            chance: loot.chance,
+        }),
+    );
+    let builder = with_opt(builder, raw.corpse.then_some(LeavesCorpse {}));
+    let builder = with_opt(builder, raw.creature);
+    let builder = with_opt(builder, raw.blocks_tile.then_some(BlocksTile {}));
+    let builder = with_opt(builder, raw.consumable.then_some(Item::Consumable));
+    let builder = with_opt(builder, raw.consumable.then_some(Consumable {}));
+    let builder = with_opt(builder, equipment_opt.map(Item::Equippable));
+    let builder = with_opt(builder, ammunition_opt.as_ref().map(|_| Item::Ammunition));
+    let builder = with_opt(builder, ammunition_opt);
+    let builder = with_opt(
+        builder,
+        raw.provides_healing
+            .map(|heal_amount| ProvidesHealing { heal_amount }),
+    );
+    let builder = with_opt(
+        builder,
+        raw.inflicts_damage.map(|damage| InflictsDamage { damage }),
+    );
+    let builder = with_opt(
+        builder,
+        raw.area_of_effect.map(|radius| AreaOfEffect { radius }),
+    );
+    let builder = with_opt(
+        builder,
+        raw.range.map(|range| Range {
+            range: AbilityRange(range),
+        }),
+    );
+    let entity = with_opt(builder, inflicts_status_opt).build();
+    outfit(ecs, entity, raw, pos, map_depth);
+    Some(entity)
+}
+
+/// Gives a new monster the equipment, items and spells its raws start it with.
+fn outfit(ecs: &mut World, owner: Entity, raw: &RawEntity, pos: Position, map_depth: i32) {
+    raw.equipped.iter().for_each(|name| {
+        if let Some(item) = spawn_named(ecs, name, pos, map_depth) {
+            ecs.write_storage::<Position>().remove(item);
+            let equipped = {
+                let items = ecs.read_storage::<Item>();
+                let equip_map = get_equipped_items(
+                    &ecs.entities(),
+                    &items,
+                    &ecs.read_storage::<Equipped>(),
+                    owner,
+                );
+                items
+                    .get(item)
+                    .and_then(Item::equip_opt)
+                    .map(|equipment| Equipped::new(owner, &equip_map, &equipment.allowed_slots))
+            };
+            if let Some(equipped) = equipped {
+                ecs.write_storage::<Equipped>()
+                    .insert(item, equipped)
+                    .expect("Unable to equip starting equipment");
+            }
+        }
+    });
+    raw.carried.iter().for_each(|name| {
+        if let Some(item) = spawn_named(ecs, name, pos, map_depth) {
+            ecs.write_storage::<Position>().remove(item);
+            ecs.write_storage::<InBackpack>()
+                .insert(item, InBackpack { owner })
+                .expect("Unable to pack starting item");
+        }
+    });
+    raw.spells.iter().for_each(|name| {
+        if let Some(spell) = spawn_named(ecs, name, pos, map_depth) {
+            // The scroll's effect without the scroll: nothing to pick up or use up
+            ecs.write_storage::<Position>().remove(spell);
+            ecs.write_storage::<Item>().remove(spell);
+            ecs.write_storage::<Consumable>().remove(spell);
+            if let Some(spell_name) = ecs.write_storage::<Name>().get_mut(spell) {
+                spell_name.name = name.strip_suffix(" Scroll").unwrap_or(name).to_string();
+            }
+            ecs.write_storage::<Spell>()
+                .insert(spell, Spell { caster: owner })
+                .expect("Unable to teach spell");
+        }
+    });
+}
+
+/// Rolls on the raws spawn table `table`, following entries that name other tables.
+pub fn spawn_from_table(
+    ecs: &mut World,
+    table: &str,
+    pos: Position,
+    map_depth: i32,
+) -> Option<Entity> {
+    let entries = raws().spawn_tables.get(table)?;
+    let name = roll_weighted(
+        &mut ecs.write_resource::<RandomNumberGenerator>(),
+        entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.weight_at(map_depth))),
+    )?;
+    if raws().spawn_tables.contains_key(name) {
+        spawn_from_table(ecs, name, pos, map_depth)
+    } else {
+        spawn_named(ecs, name, pos, map_depth)
+    }
+}
+
+/// Rolls `monster`'s loot table, dropping whatever comes up at `pos`.
+pub fn drop_loot(ecs: &mut World, monster: Entity, pos: Position) -> Option<Entity> {
+    let loot = ecs.read_storage::<LootTable>().get(monster).cloned()?;
+    let roll = ecs.write_resource::<RandomNumberGenerator>().range(0, 100);
+    if roll >= i32::from(loot.chance) {
+        return None;
+    }
+    let map_depth = ecs.fetch::<Map>().depth;
+    spawn_from_table(ecs, &loot.table, pos, map_depth)
+}
+
+/// Leaves the remains of `monster` at `pos`, if it is the kind that leaves any.
+pub fn corpse(ecs: &mut World, monster: Entity, pos: Position) -> Option<Entity> {
+    ecs.read_storage::<LeavesCorpse>().get(monster)?;
+    let name = ecs.read_storage::<Name>().get(monster)?.name.clone();
+    let fg = ecs.read_storage::<Renderable>().get(monster)?.fg;
+    let corpse = base_renderable_entity(
         ecs,
-        pos,
+        Some(pos),
         WorldEntityData {
-            name: eq_item.name(),
+            name: format!("{} corpse", name),
             renderable: Renderable {
-                glyph: bracket_lib::prelude::to_cp437('/'),
-                fg: RGB::named(dagger_material.color()),
+                glyph: bracket_lib::prelude::to_cp437('%'),
+                fg,
                 bg: RGB::named(BLACK),
-                render_order: RenderOrder::First,
+                render_order: RenderOrder::Floor,
             },
         },
-        eq_item,
     )
-    .build()
+    .with(Corpse {})
+    .build();
+    Some(corpse)
 }
 
-pub fn dagger<'a>(map_depth: i32) -> Box<SimpleSpawner<'a>> {
-    Box::new(move |ecs, pos| dagger_at_level(map_depth, ecs, pos))
+pub fn dagger_at_level(map_depth: i32, ecs: &mut World, pos: Position) -> Entity {
+    spawn_named(ecs, "Dagger", pos, map_depth).expect("no Dagger in the raws")
 }
 
 pub fn sword_at_level(map_depth: i32, ecs: &mut World, pos: Position) -> Entity {
-    let (sword_material, sword_quality) = {
-        let rng = &mut ecs.write_resource::<RandomNumberGenerator>();
-        (
-            random_blade_material(rng, map_depth),
-            random_quality(rng, map_depth),
-        )
-    };
-    let eq_item = Equipment::new(
-        ONE_HANDED,
-        Weapon(Melee(Sword)),
-        sword_material.clone(),
-        sword_quality,
-    );
-    equippable_entity(
-        ecs,
-        pos,
-        WorldEntityData {
-            name: eq_item.name(),
-            renderable: Renderable {
-                glyph: bracket_lib::prelude::to_cp437('│'),
-                fg: RGB::named(sword_material.color()),
-                bg: RGB::named(BLACK),
-                render_order: RenderOrder::First,
-            },
-        },
-        eq_item,
-    )
-    .build()
+    spawn_named(ecs, "Sword", pos, map_depth).expect("no Sword in the raws")
 }
 
-pub fn sword<'a>(map_depth: i32) -> Box<SimpleSpawner<'a>> {
-    Box::new(move |ecs, pos| sword_at_level(map_depth, ecs, pos))
+pub fn shield_at_level(map_depth: i32, ecs: &mut World, pos: Position) -> Entity {
+    spawn_named(ecs, "Shield", pos, map_depth).expect("no Shield in the raws")
 }
 
 pub fn random_quality(rng: &mut RandomNumberGenerator, map_depth: i32) -> u8 {
@@ -168,74 +395,6 @@ pub fn random_quality(rng: &mut RandomNumberGenerator, map_depth: i32) -> u8 {
     }
 }
 
-pub fn random_blade_material(rng: &mut RandomNumberGenerator, map_depth: i32) -> Material {
-    let roll = rng.range(0, 100);
-    let weighted_roll = roll + 3 * map_depth;
-    match weighted_roll {
-        0..=40 => Material::Wood,
-        41..=50 => Material::Stone,
-        51..=56 => Material::Copper,
-        57..=60 => Material::Bronze,
-        61..=70 => Material::Iron,
-        71..=75 => Material::Steel,
-        94..=98 => Material::Titanium,
-        _ => Material::DamascusSteel,
-    }
-}
-
-pub fn random_shield_material(rng: &mut RandomNumberGenerator, map_depth: i32) -> Material {
-    fn depth_table<'a>(map_depth: i32) -> RandomTable<'a, Material> {
-        let map_depth_u16 = <u32 as TryInto<u16>>::try_into(map_depth.unsigned_abs()).unwrap();
-        RandomTable::<'a, Material>::new(Material::Wood, 40_u16.saturating_sub(3 * map_depth_u16))
-            .add(Material::Copper, 20_u16.saturating_sub(2 * map_depth_u16))
-            .add(Material::Bronze, 20_u16.saturating_sub(map_depth_u16))
-            .add(Material::Iron, 10_u16.saturating_add(map_depth_u16))
-            .add(Material::Steel, 5_u16.saturating_add(2 * map_depth_u16))
-    }
-
-    let mat_table = depth_table(map_depth);
-    mat_table.roll(rng)
-}
-
-pub fn shield_at_level(map_depth: i32, ecs: &mut World, pos: Position) -> Entity {
-    let (shield_material, shield_quality) = {
-        let rng = &mut ecs.write_resource::<RandomNumberGenerator>();
-        (
-            random_shield_material(rng, map_depth),
-            random_quality(rng, map_depth),
-        )
-    };
-    let eq_item = Equipment::new(OFF_HAND, Shield, shield_material.clone(), shield_quality);
-    equippable_entity(
-        ecs,
-        pos,
-        WorldEntityData {
-            name: eq_item.name(),
-            renderable: Renderable {
-                glyph: bracket_lib::prelude::to_cp437('◙'),
-                fg: RGB::named(shield_material.color()),
-                bg: RGB::named(BLACK),
-                render_order: RenderOrder::First,
-            },
-        },
-        eq_item,
-    )
-    .build()
-}
-
-pub fn shield<'a>(map_depth: i32) -> Box<SimpleSpawner<'a>> {
-    Box::new(move |ecs, pos| shield_at_level(map_depth, ecs, pos))
-}
-
-fn ranged_consumable_entity(
-    ecs: &mut World,
-    pos: Position,
-    base_data: WorldEntityData,
-    range: AbilityRange,
-) -> EntityBuilder {
-    consumable_entity(ecs, pos, base_data).with(Range { range })
-}
-
 pub fn player(gs: &mut State, position: Position) -> Entity {
     combat_entity(
         &mut gs.ecs,
@@ -258,282 +417,59 @@ pub fn player(gs: &mut State, position: Position) -> Entity {
         },
     )
     .with(Player {})
+    .with(Initiative::new(NORMAL_SPEED))
+    .with(Experience::default())
+    .with(Faction::new(PLAYER_FACTION))
     // Note that player should not have BlocksTile; this appears to interfere with
     // the pathing algorithm used by mobs.
     .build()
 }
 
-pub fn health_potion(ecs: &mut World, position: Position) -> Entity {
-    consumable_entity(
-        ecs,
-        position,
-        WorldEntityData {
-            name: "Health Potion".into(),
-            renderable: Renderable {
-                glyph: bracket_lib::prelude::to_cp437('¡'),
-                fg: RGB::named(RED),
-                bg: RGB::named(BLACK),
-                render_order: RenderOrder::First,
-            },
-        },
-    )
-    .with(ProvidesHealing { heal_amount: 8 })
-    .build()
-}
-
-pub fn fireball_scroll(ecs: &mut World, position: Position) -> Entity {
-    ranged_consumable_entity(
-        ecs,
-        position,
-        WorldEntityData {
-            name: "Fireball Scroll".into(),
-            renderable: Renderable {
-                glyph: bracket_lib::prelude::to_cp437(')'),
-                fg: RGB::named(ORANGE),
-                bg: RGB::named(BLACK),
-                render_order: RenderOrder::First,
-            },
-        },
-        AbilityRange(6),
-    )
-    .with(InflictsDamage { damage: 20 })
-    .with(AreaOfEffect { radius: 3 })
-    .build()
-}
-
-pub fn magic_missile_scroll(ecs: &mut World, position: Position) -> Entity {
-    ranged_consumable_entity(
-        ecs,
-        position,
-        WorldEntityData {
-            name: "Magic Missile Scroll".into(),
-            renderable: Renderable {
-                glyph: bracket_lib::prelude::to_cp437(')'),
-                fg: RGB::named(CYAN),
-                bg: RGB::named(BLACK),
-                render_order: RenderOrder::First,
-            },
-        },
-        AbilityRange(6),
-    )
-    .with(InflictsDamage { damage: 8 })
-    .build()
-}
-
-pub fn confusion_scroll(ecs: &mut World, position: Position) -> Entity {
-    let rand_turns = {
-        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        rng.range(1, 7)
-    };
-    let steps: Vec<(i8, i8)> = {
-        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        (0..rand_turns)
-            .map(|_| (rng.range(-1, 2), rng.range(-1, 2)))
-            .collect()
-    };
-
-    ranged_consumable_entity(
-        ecs,
-        position,
-        WorldEntityData {
-            name: "Confusion Scroll".into(),
-            renderable: Renderable {
-                glyph: bracket_lib::prelude::to_cp437(')'),
-                fg: RGB::named(PINK),
-                bg: RGB::named(BLACK),
-                render_order: RenderOrder::First,
-            },
-        },
-        AbilityRange(6),
-    )
-    .with(Confusion {
-        step_sequence: steps,
-    })
-    .build()
-}
-
-pub fn room_table<'a, 'b>(map_depth: i32) -> RandomTable<'a, Box<SimpleSpawner<'b>>> {
-    RandomTable::<'a, Box<SimpleSpawner<'b>>>::new(
-        Box::new(health_potion) as Box<SimpleSpawner<'b>>,
-        30,
-    )
-    .add(Box::new(fireball_scroll), 30)
-    .add(Box::new(magic_missile_scroll), 40)
-    .add(Box::new(confusion_scroll), 30)
-    .add(
-        Box::new(random_monster),
-        120 + 2 * map_depth.unsigned_abs() as u16,
-    )
-    .add(dagger(map_depth), 10)
-    .add(sword(map_depth), 5)
-    .add(shield(map_depth), 10)
-}
-
-pub fn random_item(ecs: &mut World, position: Position) -> Entity {
-    let map_depth = ecs.fetch::<Map>().depth;
-    let spawn_table = room_table(map_depth);
-
-    let random_spawner = spawn_table.roll(&mut ecs.write_resource::<RandomNumberGenerator>());
-    // TODO: if we get a lot of items, may want to consider a search
-    random_spawner(ecs, position)
-}
-
-pub fn random_monster(ecs: &mut World, position: Position) -> Entity {
+pub fn random_item(ecs: &mut World, position: Position) -> Option<Entity> {
     let map_depth = ecs.fetch::<Map>().depth;
-    let pos_ix = {
-        let map = ecs.read_resource::<Map>();
-        map.pos_idx(position)
-    };
-    {
-        let mut map = ecs.write_resource::<Map>();
-        map.blocked[pos_ix] = true;
-    }
-
-    let roll = {
-        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        rng.range(0, 100) // TODO: refactor monsters as an ADT?
-                          // Some possibilities listed here: https://stackoverflow.com/questions/41637978/how-to-get-the-number-of-elements-variants-in-an-enum-as-a-constant-value
-    };
-    match roll + 2 * map_depth {
-        0..=60 => goblin(ecs, position),
-        61..=80 => orc(ecs, position),
-        81..=95 => troll(ecs, position),
-        _ => tarrasque(ecs, position),
-    }
-}
-
-struct MonsterModifiers {
-    pub damage: u16,
-    pub defense: u16,
-}
-
-fn goblin(ecs: &mut World, position: Position) -> Entity {
-    monster(
-        ecs,
-        position,
-        bracket_lib::prelude::to_cp437('g'),
-        "Goblin",
-        RGB::named(RED),
-        MonsterModifiers {
-            damage: 0,
-            defense: 0,
-        },
-    )
-}
-
-fn orc(ecs: &mut World, position: Position) -> Entity {
-    monster(
-        ecs,
-        position,
-        bracket_lib::prelude::to_cp437('o'),
-        "Orc",
-        RGB::named(GREEN),
-        MonsterModifiers {
-            damage: 0,
-            defense: 1,
-        },
-    )
-}
-
-fn troll(ecs: &mut World, position: Position) -> Entity {
-    monster(
-        ecs,
-        position,
-        bracket_lib::prelude::to_cp437('t'),
-        "Troll",
-        RGB::named(BLUE),
-        MonsterModifiers {
-            damage: 1,
-            defense: 1,
-        },
-    )
-}
-
-fn tarrasque(ecs: &mut World, position: Position) -> Entity {
-    monster(
-        ecs,
-        position,
-        bracket_lib::prelude::to_cp437('T'),
-        "Tarrasque",
-        RGB::named(YELLOW),
-        MonsterModifiers {
-            damage: 2,
-            defense: 1,
-        },
-    )
-}
-
-fn monster<S: ToString>(
-    ecs: &mut World,
-    posn: Position,
-    glyph: FontCharType,
-    name: S,
-    fg: RGB,
-    mods: MonsterModifiers,
-) -> Entity {
-    combat_entity(
-        ecs,
-        posn,
-        WorldEntityData {
-            name: name.to_string(),
-            renderable: Renderable {
-                glyph,
-                fg,
-                bg: RGB::named(BLACK),
-                render_order: RenderOrder::Second,
-            },
-        },
-        Some(ViewRange(8)),
-        CombatStats {
-            max_hp: 16,
-            hp: 16,
-            defense: 1 + mods.defense,
-            power: 4 + mods.damage,
-        },
-    )
-    .with(Monster {})
-    .with(BlocksTile {})
-    .build()
+    spawn_from_table(ecs, ROOM_TABLE, position, map_depth)
 }
 
-pub fn spawn_room(ecs: &mut World, room: &Rect, map_depth: i32) -> Vec<Entity> {
+pub fn spawn_region(ecs: &mut World, region: &[usize], map_depth: i32) -> Vec<Entity> {
     let num_entities = {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
         rng.roll_dice(1, INIT_MAX_SPAWN as i32) + (map_depth.abs() - 1)
     }
     .try_into()
     .unwrap();
-    spawn_in_room(ecs, room, num_entities, random_item)
+    spawn_in_region(ecs, region, num_entities, random_item)
 }
 
-/// Fills a room with monsters and items
-pub fn spawn_in_room(
+/// Fills a region (usually a room) with monsters and items
+pub fn spawn_in_region(
     ecs: &mut World,
-    room: &Rect,
+    region: &[usize],
     num_indices: u16,
-    spawn_fn: fn(&mut World, Position) -> Entity,
+    spawn_fn: fn(&mut World, Position) -> Option<Entity>,
 ) -> Vec<Entity> {
     let fill_indices: Vec<usize> = {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
         let map = &mut ecs.fetch_mut::<Map>();
 
-        let mut free_room_indices: Vec<usize> = (room.x1..=room.x2)
-            .cartesian_product(room.y1..=room.y2)
-            .map(|(x, y)| map.xy_idx(x, y))
+        let mut free_region_indices: Vec<usize> = region
+            .iter()
+            .copied()
             .filter(|ix| !map.blocked[*ix] && map.tile_content[*ix].is_empty())
             .collect();
 
         (0..num_indices)
-            .map(|_| {
-                let idx = rng.range(0, free_room_indices.len());
-                free_room_indices.remove(idx)
+            .map_while(|_| {
+                (!free_region_indices.is_empty()).then(|| {
+                    let idx = rng.range(0, free_region_indices.len());
+                    free_region_indices.remove(idx)
+                })
             })
             .collect()
     };
 
     fill_indices
         .iter()
-        .map(|ix| {
+        .filter_map(|ix| {
             let pos = {
                 let map = &mut ecs.fetch_mut::<Map>();
                 map.idx_to_pos(*ix)
@@ -544,11 +480,11 @@ pub fn spawn_in_room(
                     xx: pos.xx,
                     yy: pos.yy,
                 },
-            );
+            )?;
             let map = &mut ecs.fetch_mut::<Map>();
             map.tile_content[*ix].push(entity);
             // TODO: ^ make our own entity wrapper to avoid having to remember to do this
-            entity
+            Some(entity)
         })
         .collect()
 }
@@ -0,0 +1,290 @@
+use std::ops::Deref;
+
+use specs::{prelude::*, storage::MaskedStorage};
+
+use crate::{
+    components::{
+        CombatStats, EventIncomingDamage, Player, Position, StatusEffect, StatusEffects,
+        StatusKind, Viewshed,
+    },
+    gamelog::GameLog,
+    RunState,
+};
+
+/// How far the blind can see.
+pub const BLIND_VIEW_RANGE: i32 = 1;
+
+/// How a second dose of an effect combines with the one already there.
+enum Stacking {
+    /// Magnitudes add up, and the longer duration is kept
+    Intensify,
+    /// The stronger magnitude and the longer duration are kept
+    Refresh,
+    /// Durations add up
+    Extend,
+}
+
+impl StatusKind {
+    fn stacking(&self) -> Stacking {
+        match self {
+            StatusKind::Poison => Stacking::Intensify,
+            StatusKind::Regeneration | StatusKind::Haste | StatusKind::Slow | StatusKind::Blind => {
+                Stacking::Refresh
+            }
+            StatusKind::Confusion => Stacking::Extend,
+        }
+    }
+
+    /// Haste and slow cancel each other out.
+    fn opposite(&self) -> Option<StatusKind> {
+        match self {
+            StatusKind::Haste => Some(StatusKind::Slow),
+            StatusKind::Slow => Some(StatusKind::Haste),
+            _ => None,
+        }
+    }
+
+    /// As in "You are no longer poisoned."
+    pub fn adjective(&self) -> &'static str {
+        match self {
+            StatusKind::Poison => "poisoned",
+            StatusKind::Regeneration => "regenerating",
+            StatusKind::Haste => "hasted",
+            StatusKind::Slow => "slowed",
+            StatusKind::Blind => "blind",
+            StatusKind::Confusion => "confused",
+        }
+    }
+
+    /// Shown in the UI while the effect lasts.
+    pub fn label(&self) -> &'static str {
+        match self {
+            StatusKind::Poison => "Poisoned",
+            StatusKind::Regeneration => "Regen",
+            StatusKind::Haste => "Hasted",
+            StatusKind::Slow => "Slowed",
+            StatusKind::Blind => "Blind",
+            StatusKind::Confusion => "Confused",
+        }
+    }
+}
+
+impl StatusEffects {
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Adds `effect`, stacking it with any effect of the same kind.
+    pub fn add(&mut self, effect: StatusEffect) {
+        if let Some(opposite) = effect.kind.opposite() {
+            self.effects.retain(|existing| existing.kind != opposite);
+        }
+        match self
+            .effects
+            .iter_mut()
+            .find(|existing| existing.kind == effect.kind)
+        {
+            None => self.effects.push(effect),
+            Some(existing) => match effect.kind.stacking() {
+                Stacking::Intensify => {
+                    existing.magnitude += effect.magnitude;
+                    existing.turns_left = existing.turns_left.max(effect.turns_left);
+                }
+                Stacking::Refresh => {
+                    existing.magnitude = existing.magnitude.max(effect.magnitude);
+                    existing.turns_left = existing.turns_left.max(effect.turns_left);
+                }
+                Stacking::Extend => existing.turns_left += effect.turns_left,
+            },
+        }
+    }
+
+    /// Counts every effect down a turn; returns the kinds that wore off.
+    pub fn tick(&mut self) -> Vec<StatusKind> {
+        self.effects
+            .iter_mut()
+            .for_each(|effect| effect.turns_left = effect.turns_left.saturating_sub(1));
+        let worn_off = self
+            .effects
+            .iter()
+            .filter(|effect| effect.turns_left == 0)
+            .map(|effect| effect.kind)
+            .collect();
+        self.effects.retain(|effect| effect.turns_left > 0);
+        worn_off
+    }
+}
+
+pub fn has_status<D>(statuses: &Storage<StatusEffects, D>, entity: Entity, kind: StatusKind) -> bool
+where
+    D: Deref<Target = MaskedStorage<StatusEffects>>,
+{
+    statuses
+        .get(entity)
+        .map_or(false, |effects| effects.has(kind))
+}
+
+/// Adds `effect` to `entity`, which need not have any effects yet.
+pub fn inflict(statuses: &mut WriteStorage<StatusEffects>, entity: Entity, effect: StatusEffect) {
+    if let Ok(entry) = statuses.entry(entity) {
+        entry.or_insert_with(StatusEffects::default).add(effect);
+    }
+}
+
+/// Applies poison and regeneration and counts effects down, once per player turn.
+pub struct StatusEffectSystem {}
+
+impl<'a> System<'a> for StatusEffectSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, GameLog>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, StatusEffects>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, EventIncomingDamage>,
+        WriteStorage<'a, Viewshed>,
+        ReadStorage<'a, Position>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            runstate,
+            mut log,
+            players,
+            mut statuses,
+            mut combat_stats,
+            mut incoming_damage,
+            mut viewsheds,
+            positions,
+        ) = data;
+
+        if *runstate != RunState::PlayerTurn {
+            return;
+        }
+        let mut cured = vec![];
+        // Those frozen on other levels have no position, and wait for the player to return
+        (&entities, &mut statuses, &positions)
+            .join()
+            .for_each(|(entity, effects, _pos)| {
+                let is_player = players.get(entity).is_some();
+                effects.effects.iter().for_each(|effect| match effect.kind {
+                    StatusKind::Poison => {
+                        EventIncomingDamage::new_damage(
+                            &mut incoming_damage,
+                            entity,
+                            effect.magnitude,
+                        );
+                        if is_player {
+                            log.entries
+                                .push(format!("You take {} poison damage.", effect.magnitude));
+                        }
+                    }
+                    StatusKind::Regeneration => {
+                        if let Some(stats) = combat_stats.get_mut(entity) {
+                            stats.hp = stats.max_hp.min(stats.hp + effect.magnitude);
+                        }
+                    }
+                    _ => {}
+                });
+
+                let worn_off = effects.tick();
+                if worn_off.contains(&StatusKind::Blind) {
+                    if let Some(viewshed) = viewsheds.get_mut(entity) {
+                        viewshed.dirty = true;
+                    }
+                }
+                if is_player {
+                    worn_off.iter().for_each(|kind| {
+                        log.entries
+                            .push(format!("You are no longer {}.", kind.adjective()))
+                    });
+                }
+                if effects.effects.is_empty() {
+                    cured.push(entity);
+                }
+            });
+        cured.into_iter().for_each(|entity| {
+            statuses.remove(entity);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dungeon::freeze_level_entities,
+        init_state,
+        player::{get_player_pos_unwrap, PLAYER_NAME},
+        spawner,
+    };
+
+    use super::*;
+
+    fn effect(kind: StatusKind, turns_left: u16, magnitude: u16) -> StatusEffect {
+        StatusEffect {
+            kind,
+            turns_left,
+            magnitude,
+        }
+    }
+
+    #[test]
+    fn effects_stack_by_kind() {
+        let mut statuses = StatusEffects::default();
+        statuses.add(effect(StatusKind::Poison, 3, 1));
+        statuses.add(effect(StatusKind::Poison, 5, 2));
+        statuses.add(effect(StatusKind::Confusion, 2, 1));
+        statuses.add(effect(StatusKind::Confusion, 3, 1));
+        statuses.add(effect(StatusKind::Haste, 4, 1));
+        statuses.add(effect(StatusKind::Slow, 6, 1));
+        assert_eq!(
+            statuses.effects,
+            vec![
+                effect(StatusKind::Poison, 5, 3),
+                effect(StatusKind::Confusion, 5, 1),
+                effect(StatusKind::Slow, 6, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn frozen_levels_wait_for_the_player() {
+        let (mut gs, _) = init_state(true, None);
+        let player_posn = get_player_pos_unwrap(&gs.ecs, PLAYER_NAME);
+        let goblin = spawner::spawn_named(&mut gs.ecs, "Goblin", player_posn, 1).unwrap();
+        inflict(
+            &mut gs.ecs.write_storage::<StatusEffects>(),
+            goblin,
+            effect(StatusKind::Poison, 3, 2),
+        );
+        freeze_level_entities(&gs.ecs);
+        gs.ecs.insert(RunState::PlayerTurn);
+
+        StatusEffectSystem {}.run_now(&gs.ecs);
+
+        assert!(gs
+            .ecs
+            .read_storage::<EventIncomingDamage>()
+            .get(goblin)
+            .is_none());
+        let statuses = gs.ecs.read_storage::<StatusEffects>();
+        assert_eq!(
+            statuses.get(goblin).map(|effects| effects.effects.clone()),
+            Some(vec![effect(StatusKind::Poison, 3, 2)])
+        );
+    }
+
+    #[test]
+    fn ticking_reports_what_wore_off() {
+        let mut statuses = StatusEffects::default();
+        statuses.add(effect(StatusKind::Blind, 1, 1));
+        statuses.add(effect(StatusKind::Regeneration, 2, 1));
+        assert_eq!(statuses.tick(), vec![StatusKind::Blind]);
+        assert_eq!(
+            statuses.effects,
+            vec![effect(StatusKind::Regeneration, 1, 1)]
+        );
+    }
+}
@@ -127,3 +127,33 @@ pub fn pluralize_verb_if(pred: bool) -> fn(&str) -> String {
         |word: &str| pluralize_verb(word)
     }
 }
+
+/// Formats seconds since the Unix epoch as "YYYY-MM-DD HH:MM UTC".
+pub fn fmt_utc_timestamp(secs: u64) -> String {
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days, counting in 400-year eras starting 0000-03-01
+    let zz = days as i64 + 719_468;
+    let era = zz.div_euclid(146_097);
+    let doe = zz.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60
+    )
+}
+
+#[test]
+fn fmt_utc_timestamp_test() {
+    assert_eq!(fmt_utc_timestamp(0), "1970-01-01 00:00 UTC");
+    assert_eq!(fmt_utc_timestamp(951_782_400), "2000-02-29 00:00 UTC");
+    assert_eq!(fmt_utc_timestamp(1_792_243_380), "2026-10-17 13:23 UTC");
+}
@@ -1,4 +1,7 @@
-use crate::components::{Player, Positionable};
+use crate::{
+    components::{Equipped, Item, Player, Positionable, StatusEffects, StatusKind},
+    status_effect_system::{has_status, BLIND_VIEW_RANGE},
+};
 
 use super::{Map, Position, Viewshed};
 use bracket_lib::prelude::field_of_view;
@@ -13,10 +16,13 @@ impl<'a> System<'a> for VisibilitySystem {
         WriteStorage<'a, Viewshed>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Player>,
+        ReadStorage<'a, Item>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, StatusEffects>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, entities, mut viewshed, pos, player) = data;
+        let (mut map, entities, mut viewshed, pos, player, items, equipped, statuses) = data;
 
         (&entities, &mut viewshed, &pos)
             .join()
@@ -24,7 +30,18 @@ impl<'a> System<'a> for VisibilitySystem {
                 if viewshed.dirty {
                     viewshed.dirty = false;
                     viewshed.visible_tiles.clear();
-                    viewshed.visible_tiles = field_of_view((*pos).into(), viewshed.range.0, &*map);
+                    let range_bonus = (&items, &equipped)
+                        .join()
+                        .filter(|(_, eq)| eq.owner == ent)
+                        .filter_map(|(item, _)| item.equip_opt())
+                        .map(|eq| eq.view_range_bonus())
+                        .sum::<i32>();
+                    let range = if has_status(&statuses, ent, StatusKind::Blind) {
+                        BLIND_VIEW_RANGE
+                    } else {
+                        viewshed.range.0 + range_bonus
+                    };
+                    viewshed.visible_tiles = field_of_view((*pos).into(), range, &*map);
                     viewshed.visible_tiles.retain(|pt| {
                         pt.x >= 0
                             && pt.x < map.width().try_into().unwrap()